@@ -0,0 +1,157 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+const DAY: u64 = 24 * 3_600;
+const WEEK: u64 = 7 * DAY;
+const YEAR: u64 = 365 * DAY;
+
+/// Upper limits in seconds of the age ranges.
+pub const DEFAULT_AGE_LIMITS: [u64; 4] = [DAY, WEEK, 30 * DAY, YEAR];
+
+/// Timestamp used to compute the age of the files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TimeField {
+    /// Last modification, mtime.
+    Modified,
+    /// Last access, atime.
+    Accessed,
+    /// Last status change, ctime. Not available on Windows.
+    Changed,
+    /// Creation, btime. Not supported by every filesystem.
+    Created,
+}
+
+impl FromStr for TimeField {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "mtime" => Ok(TimeField::Modified),
+            "atime" => Ok(TimeField::Accessed),
+            "ctime" => Ok(TimeField::Changed),
+            "btime" => Ok(TimeField::Created),
+            _ => Err(format!(
+                "unknown time \"{s}\", expect mtime, atime, ctime or btime"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for TimeField {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TimeField::Modified => write!(f, "mtime"),
+            TimeField::Accessed => write!(f, "atime"),
+            TimeField::Changed => write!(f, "ctime"),
+            TimeField::Created => write!(f, "btime"),
+        }
+    }
+}
+
+/// An age range of the report: files from `min` seconds old included to `max` excluded.
+/// The last range has no upper limit.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgeBucket {
+    pub min: u64,
+    pub max: Option<u64>,
+    pub files: usize,
+    pub bytes: u64,
+}
+
+impl AgeBucket {
+    /// Label used in the terminal report, like "Between 1 day and 1 week".
+    pub fn label(&self) -> String {
+        match self.max {
+            Some(max) if self.min == 0 => format!("Less than {}", long_age(max)),
+            Some(max) => format!("Between {} and {}", long_age(self.min), long_age(max)),
+            None => format!("More than {}", long_age(self.min)),
+        }
+    }
+
+    /// Short label used in the matrix table, like "1d-1w".
+    pub fn short_label(&self) -> String {
+        match self.max {
+            Some(max) if self.min == 0 => format!("<{}", short_age(max)),
+            Some(max) => format!("{}-{}", short_age(self.min), short_age(max)),
+            None => format!(">{}", short_age(self.min)),
+        }
+    }
+
+    /// Column name used in the CSV header, like "1d_1w".
+    pub fn csv_name(&self) -> String {
+        match self.max {
+            Some(max) if self.min == 0 => format!("Less_than_{}", short_age(max)),
+            Some(max) => format!("{}_{}", short_age(self.min), short_age(max)),
+            None => short_age(self.min),
+        }
+    }
+}
+
+/// Build the list of age ranges from their upper limits in seconds.
+pub fn build_age_buckets(limits: &[u64]) -> Vec<AgeBucket> {
+    let mut buckets = Vec::with_capacity(limits.len() + 1);
+    let mut min = 0;
+    for limit in limits {
+        buckets.push(AgeBucket {
+            min,
+            max: Some(*limit),
+            files: 0,
+            bytes: 0,
+        });
+        min = *limit;
+    }
+    buckets.push(AgeBucket {
+        min,
+        max: None,
+        files: 0,
+        bytes: 0,
+    });
+    buckets
+}
+
+/// Age in seconds of a file at the given time, both in seconds since the epoch.
+/// A time in the future gives an age of 0.
+pub fn age(now: i64, time: i64) -> u64 {
+    u64::try_from(now.saturating_sub(time)).unwrap_or(0)
+}
+
+/// Index of the age range of a file of the given age in seconds.
+pub fn age_index(limits: &[u64], age: u64) -> usize {
+    limits.partition_point(|limit| *limit <= age)
+}
+
+const AGE_UNITS: [(u64, &str, &str); 5] = [
+    (YEAR, "year", "y"),
+    (WEEK, "week", "w"),
+    (DAY, "day", "d"),
+    (3_600, "hour", "h"),
+    (60, "minute", "m"),
+];
+
+/// Display an age with the biggest unit dividing it exactly, like "30 days".
+fn long_age(secs: u64) -> String {
+    for (factor, unit, _) in AGE_UNITS {
+        if secs >= factor && secs.is_multiple_of(factor) {
+            let n = secs / factor;
+            return if n == 1 {
+                format!("1 {unit}")
+            } else {
+                format!("{n} {unit}s")
+            };
+        }
+    }
+    format!("{secs} seconds")
+}
+
+/// Display an age with the biggest unit dividing it exactly, like "30d".
+fn short_age(secs: u64) -> String {
+    for (factor, _, unit) in AGE_UNITS {
+        if secs >= factor && secs.is_multiple_of(factor) {
+            return format!("{}{unit}", secs / factor);
+        }
+    }
+    format!("{secs}s")
+}
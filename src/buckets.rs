@@ -0,0 +1,274 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+/// Upper limits in bytes of the default size ranges.
+pub const DEFAULT_LIMITS: [u64; 12] = [
+    4_000,
+    8_000,
+    16_000,
+    32_000,
+    64_000,
+    128_000,
+    256_000,
+    512_000,
+    1_000_000,
+    10_000_000,
+    100_000_000,
+    1_000_000_000,
+];
+
+/// Upper limits in bytes of the default size ranges with binary units.
+pub const DEFAULT_BINARY_LIMITS: [u64; 12] = [
+    4 << 10,
+    8 << 10,
+    16 << 10,
+    32 << 10,
+    64 << 10,
+    128 << 10,
+    256 << 10,
+    512 << 10,
+    1 << 20,
+    10 << 20,
+    100 << 20,
+    1 << 30,
+];
+
+const DECIMAL_UNITS: [(u64, &str); 4] = [
+    (1_000_000_000_000, "T"),
+    (1_000_000_000, "G"),
+    (1_000_000, "M"),
+    (1_000, "K"),
+];
+
+const BINARY_UNITS: [(u64, &str); 4] = [
+    (1 << 40, "Ti"),
+    (1 << 30, "Gi"),
+    (1 << 20, "Mi"),
+    (1 << 10, "Ki"),
+];
+
+/// Units used to read and display the limits of the size ranges.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Unit {
+    /// Powers of 1000: K, M, G and T.
+    #[default]
+    Decimal,
+    /// Powers of 1024: Ki, Mi, Gi and Ti.
+    Binary,
+}
+
+impl Unit {
+    /// Upper limits of the default size ranges for this unit.
+    pub fn default_limits(&self) -> Vec<u64> {
+        match self {
+            Unit::Decimal => DEFAULT_LIMITS.to_vec(),
+            Unit::Binary => DEFAULT_BINARY_LIMITS.to_vec(),
+        }
+    }
+
+    /// Display a size with one decimal in the biggest fitting unit, like "1.5 MB" or "1.5 MiB".
+    pub fn human_size(&self, size: u64) -> String {
+        for (factor, u) in self.units() {
+            if size >= *factor {
+                return format!("{:.1} {u}B", size as f64 / *factor as f64);
+            }
+        }
+        format!("{size} B")
+    }
+
+    fn units(&self) -> &'static [(u64, &'static str); 4] {
+        match self {
+            Unit::Decimal => &DECIMAL_UNITS,
+            Unit::Binary => &BINARY_UNITS,
+        }
+    }
+}
+
+impl FromStr for Unit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "decimal" => Ok(Unit::Decimal),
+            "binary" => Ok(Unit::Binary),
+            _ => Err(format!("unknown unit \"{s}\", expect decimal or binary")),
+        }
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Unit::Decimal => write!(f, "decimal"),
+            Unit::Binary => write!(f, "binary"),
+        }
+    }
+}
+
+/// A size range of the report: non empty files from `min` included to `max` excluded.
+/// The last range has no upper limit.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bucket {
+    pub min: u64,
+    pub max: Option<u64>,
+    pub files: usize,
+    pub bytes: u64,
+    /// Allocated size on disk, only collected on demand.
+    pub allocated_bytes: u64,
+}
+
+impl Bucket {
+    /// Label used in the terminal report, like "Between 4KB and 8KB" or "Between 4KiB and 8KiB".
+    pub fn label(&self, unit: Unit) -> String {
+        match self.max {
+            Some(max) if self.min == 0 => format!("Less than {}", short_size(max, unit)),
+            Some(max) => format!(
+                "Between {}B and {}B",
+                short_size(self.min, unit),
+                short_size(max, unit)
+            ),
+            None => format!("More than {}B", short_size(self.min, unit)),
+        }
+    }
+
+    /// Column name used in the CSV header, like "4K_8K" or "4Ki_8Ki".
+    pub fn csv_name(&self, unit: Unit) -> String {
+        match self.max {
+            Some(max) if self.min == 0 => format!("Less_than_{}", short_size(max, unit)),
+            Some(max) => format!("{}_{}", short_size(self.min, unit), short_size(max, unit)),
+            None => short_size(self.min, unit),
+        }
+    }
+}
+
+/// Build the list of ranges from the upper limits. The limits must be sorted and not null.
+pub fn build_buckets(limits: &[u64]) -> Vec<Bucket> {
+    let mut buckets = Vec::with_capacity(limits.len() + 1);
+    let mut min = 0;
+    for limit in limits {
+        buckets.push(Bucket {
+            min,
+            max: Some(*limit),
+            files: 0,
+            bytes: 0,
+            allocated_bytes: 0,
+        });
+        min = *limit;
+    }
+    buckets.push(Bucket {
+        min,
+        max: None,
+        files: 0,
+        bytes: 0,
+        allocated_bytes: 0,
+    });
+    buckets
+}
+
+/// Index of the size range of a non empty file, the limits being the upper limits of the ranges.
+pub fn bucket_index(limits: &[u64], len: u64) -> usize {
+    limits.partition_point(|limit| *limit <= len)
+}
+
+/// Parse a list of limits separated by commas or new lines, like "4K,8K,1M".
+/// Empty lines and lines starting with `#` are ignored.
+pub fn parse_limits(input: &str, unit: Unit) -> Result<Vec<u64>, String> {
+    let mut limits = Vec::new();
+    for line in input.lines() {
+        let line = line.trim();
+        if line.starts_with('#') {
+            continue;
+        }
+        for value in line.split(',') {
+            let value = value.trim();
+            if value.is_empty() {
+                continue;
+            }
+            limits.push(parse_size(value, unit)?);
+        }
+    }
+
+    if limits.is_empty() {
+        return Err("no bucket limit given".to_string());
+    }
+    if limits[0] == 0 {
+        return Err("bucket limits must be greater than 0".to_string());
+    }
+    if limits.windows(2).any(|w| w[0] >= w[1]) {
+        return Err("bucket limits must be in increasing order".to_string());
+    }
+
+    Ok(limits)
+}
+
+/// Parse a size in bytes with an optional unit, like "4000", "4K", "4KB" or "4KiB".
+/// K, M, G and T follow the given unit while Ki, Mi, Gi and Ti are always binary.
+pub fn parse_size(input: &str, unit: Unit) -> Result<u64, String> {
+    let upper = input.trim().to_uppercase();
+    let mut value = upper.strip_suffix('B').unwrap_or(&upper);
+    let mut unit = unit;
+    if let Some(v) = value.strip_suffix('I') {
+        value = v;
+        unit = Unit::Binary;
+    }
+
+    let (number, factor) = match unit.units().iter().find(|(_, u)| value.ends_with(&u[..1])) {
+        Some((factor, _)) => (&value[..value.len() - 1], *factor),
+        None => (value, 1),
+    };
+
+    match number.trim().parse::<u64>() {
+        Ok(n) => n
+            .checked_mul(factor)
+            .ok_or_else(|| format!("size is too big: {input}")),
+        Err(e) => Err(format!("can't parse size \"{input}\": {e}")),
+    }
+}
+
+/// Display a size with the biggest unit dividing it exactly, like "4K" for 4000 or "4Ki" for 4096.
+fn short_size(size: u64, unit: Unit) -> String {
+    for (factor, u) in unit.units() {
+        if size >= *factor && size.is_multiple_of(*factor) {
+            return format!("{}{u}", size / factor);
+        }
+    }
+    size.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_size_units() {
+        assert_eq!(parse_size("4000", Unit::Decimal), Ok(4_000));
+        assert_eq!(parse_size("4K", Unit::Decimal), Ok(4_000));
+        assert_eq!(parse_size("4kb", Unit::Decimal), Ok(4_000));
+        assert_eq!(parse_size(" 2T ", Unit::Decimal), Ok(2_000_000_000_000));
+        assert!(parse_size("four", Unit::Decimal).is_err());
+        assert!(parse_size("20000000T", Unit::Decimal).is_err());
+    }
+
+    #[test]
+    fn parse_limits_lines() {
+        let limits = parse_limits("# small files\n4K, 64K\n\n1M\n", Unit::Decimal);
+        assert_eq!(limits, Ok(vec![4_000, 64_000, 1_000_000]));
+        assert!(parse_limits("# nothing", Unit::Decimal).is_err());
+        assert!(parse_limits("0,4K", Unit::Decimal).is_err());
+        assert!(parse_limits("1M,4K", Unit::Decimal).is_err());
+        assert!(parse_limits("4K,4000", Unit::Decimal).is_err());
+    }
+
+    #[test]
+    fn parse_size_binary_units() {
+        assert_eq!(parse_size("4K", Unit::Binary), Ok(4_096));
+        assert_eq!(parse_size("4KiB", Unit::Decimal), Ok(4_096));
+        assert_eq!(parse_size("1Mi", Unit::Decimal), Ok(1_048_576));
+        assert_eq!(
+            parse_limits("4Ki,1M", Unit::Binary),
+            Ok(vec![4_096, 1_048_576])
+        );
+    }
+}
@@ -0,0 +1,157 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::objects::{self, DirStats, ExtensionStats, OwnerStats};
+
+/// State of an unfinished scan: the totals of the directories already scanned and the directories left.
+///
+/// It's saved periodically by [`crate::Scanner::checkpoint`], and a killed scan continues from it
+/// with [`crate::Scanner::resume`] instead of starting over.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    version: String,
+    /// Settings of the scan, a resumed scan must use the same ones.
+    pub(crate) settings: String,
+    pub(crate) path: RawPath,
+    /// Time spent scanning before the checkpoint.
+    pub(crate) elapsed: Duration,
+    /// Counters of the directories already scanned, the ranked lists are empty.
+    pub(crate) result: objects::Result,
+    pub(crate) dir_totals: Vec<(RawPath, DirStats)>,
+    pub(crate) users: Vec<(u32, OwnerStats)>,
+    pub(crate) groups: Vec<(u32, OwnerStats)>,
+    pub(crate) extensions: Vec<(String, ExtensionStats)>,
+    pub(crate) largest: Vec<(u64, RawPath)>,
+    pub(crate) linked_inodes: Vec<(u64, u64)>,
+    /// Directories queued or being scanned at the time of the checkpoint, with their depth.
+    pub(crate) pending: Vec<(RawPath, usize)>,
+    /// Subdirectories already queued by the directories being scanned, not queued again when they are
+    /// scanned again.
+    pub(crate) sent: Vec<(RawPath, Vec<RawPath>)>,
+}
+
+impl Checkpoint {
+    pub(crate) fn new(settings: String, path: PathBuf, result: objects::Result) -> Checkpoint {
+        Checkpoint {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            settings,
+            path: RawPath(path),
+            elapsed: Duration::new(0, 0),
+            result,
+            dir_totals: Vec::new(),
+            users: Vec::new(),
+            groups: Vec::new(),
+            extensions: Vec::new(),
+            largest: Vec::new(),
+            linked_inodes: Vec::new(),
+            pending: Vec::new(),
+            sent: Vec::new(),
+        }
+    }
+
+    /// Read a checkpoint saved by the same version of the tool.
+    pub fn load(file: &Path) -> Result<Checkpoint, String> {
+        let reader = match File::open(file) {
+            Ok(f) => BufReader::new(f),
+            Err(e) => return Err(format!("can't open {file:?}: {e}")),
+        };
+        let checkpoint: Checkpoint = match serde_json::from_reader(reader) {
+            Ok(c) => c,
+            Err(e) => return Err(format!("can't read the checkpoint {file:?}: {e}")),
+        };
+        if checkpoint.version != env!("CARGO_PKG_VERSION") {
+            return Err(format!(
+                "the checkpoint was saved by version {}, expect {}",
+                checkpoint.version,
+                env!("CARGO_PKG_VERSION")
+            ));
+        }
+        Ok(checkpoint)
+    }
+
+    /// Write the checkpoint next to the file then rename it, so a crash never leaves a truncated file.
+    pub(crate) fn save(&self, file: &Path) -> std::io::Result<()> {
+        let mut tmp = file.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let mut writer = BufWriter::new(File::create(&tmp)?);
+        serde_json::to_writer(&mut writer, self)?;
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+        fs::rename(&tmp, file)
+    }
+
+    /// Scanned path.
+    pub fn path(&self) -> &Path {
+        &self.path.0
+    }
+
+    /// Number of directories left to scan.
+    pub fn pending_dirs(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+/// Path saved from its raw bytes, as a string when it's valid UTF-8 and as a list of bytes otherwise.
+#[derive(Debug, Clone)]
+pub(crate) struct RawPath(pub(crate) PathBuf);
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum Encoded {
+    Text(String),
+    Bytes(Vec<u8>),
+}
+
+impl Serialize for RawPath {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        raw_path::serialize(&self.0, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for RawPath {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        raw_path::deserialize(deserializer).map(RawPath)
+    }
+}
+
+/// Path fields saved from their raw bytes, with `#[serde(with = "crate::checkpoint::raw_path")]`.
+pub(crate) mod raw_path {
+    use super::Encoded;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::path::{Path, PathBuf};
+
+    pub(crate) fn serialize<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+        let encoded = match path.to_str() {
+            Some(s) => Encoded::Text(s.to_string()),
+            #[cfg(unix)]
+            None => {
+                use std::os::unix::ffi::OsStrExt;
+                Encoded::Bytes(path.as_os_str().as_bytes().to_vec())
+            }
+            #[cfg(not(unix))]
+            None => Encoded::Text(path.to_string_lossy().to_string()),
+        };
+        encoded.serialize(serializer)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<PathBuf, D::Error> {
+        let path = match Encoded::deserialize(deserializer)? {
+            Encoded::Text(s) => PathBuf::from(s),
+            #[cfg(unix)]
+            Encoded::Bytes(bytes) => {
+                use std::ffi::OsString;
+                use std::os::unix::ffi::OsStringExt;
+                PathBuf::from(OsString::from_vec(bytes))
+            }
+            #[cfg(not(unix))]
+            Encoded::Bytes(bytes) => PathBuf::from(String::from_utf8_lossy(&bytes).to_string()),
+        };
+        Ok(path)
+    }
+}
@@ -0,0 +1,136 @@
+use clap::{Parser, ValueEnum};
+
+use std::path::PathBuf;
+
+use fs_scan::ages::TimeField;
+use fs_scan::buckets::Unit;
+
+/// Scan recursively the given directory and generate a report of the scanned files based on their relative size.
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+pub struct Config {
+    /// Directory to scan. Not needed with --resume, the scan continues on the path of the checkpoint.
+    #[arg(required_unless_present = "resume")]
+    pub path: Option<PathBuf>,
+
+    /// Maximum number of parallel threads. If not configured, 4 times the number of detected logical CPU.
+    #[arg(short = 't', long, default_value_t = 0)]
+    pub max_threads: usize,
+    /// If specified a CSV log file is generated. Multiple run can be done from the same directory to collect outputs from multiple directories in a single file.
+    #[arg(short, long)]
+    pub save_csv: bool,
+
+    /// Number of directories listed in the reports of the directories with the most files and the most bytes. If 0 the reports are disabled.
+    #[arg(long, default_value_t = 0)]
+    pub top_dirs: usize,
+
+    /// Maximum depth, relative to the scanned path, of the directories listed in the top directories reports.
+    #[arg(long, default_value_t = 3)]
+    pub top_dirs_depth: usize,
+
+    /// Number of largest files listed with their path after the size ranges. If 0 the list is disabled.
+    #[arg(long, default_value_t = 0)]
+    pub largest_files: usize,
+
+    /// If specified the allocated size on disk is collected next to the apparent size. No effect on Windows target.
+    #[arg(short, long)]
+    pub allocated: bool,
+
+    /// Glob pattern of the entries to skip, can be repeated. Excluded directories are not scanned. A pattern with a / is matched against the full path, otherwise against the entry name.
+    #[arg(short, long)]
+    pub exclude: Vec<String>,
+
+    /// File listing exclude patterns, one per line. Lines starting with # are ignored.
+    #[arg(long)]
+    pub exclude_from: Option<String>,
+
+    /// Glob pattern of the files to count, can be repeated. If specified the other files are skipped, directories are still scanned.
+    #[arg(short, long)]
+    pub include: Vec<String>,
+
+    /// If specified the directories on another filesystem than the given path are skipped. No effect on Windows target.
+    #[arg(short = 'x', long)]
+    pub one_file_system: bool,
+
+    /// Maximum depth of the scanned entries, the entries of the given path being at depth 1. The directories at this depth are counted but not scanned.
+    #[arg(short = 'd', long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub max_depth: Option<u64>,
+
+    /// If specified the number of files, their size and the number of directories are displayed per depth.
+    #[arg(long)]
+    pub depth_report: bool,
+
+    /// If specified the files are also reported per age, computed from the given timestamp: mtime, atime, ctime or btime.
+    #[arg(long)]
+    pub age: Option<TimeField>,
+
+    /// If specified the files are also reported per size and age ranges, to see for instance the bytes in big files not used for a year.
+    #[arg(long, requires = "age")]
+    pub matrix: bool,
+
+    /// If specified the number of files, their size and the number of small files are displayed per user and group. No effect on Windows.
+    #[arg(long)]
+    pub owners: bool,
+
+    /// Number of extensions listed, with their size ranges, in the reports of the extensions with the most files and the most bytes. If 0 the reports are disabled. Every distinct extension is kept in memory until the end of the scan, the ones longer than 16 characters are grouped together.
+    #[arg(long, default_value_t = 0)]
+    pub top_extensions: usize,
+
+    /// If specified the extensions are grouped regardless of their case, like .JPG and .jpg.
+    #[arg(long)]
+    pub fold_case: bool,
+
+    /// Format of the report.
+    #[arg(short, long, value_enum, default_value_t = Format::Text)]
+    pub format: Format,
+
+    /// File to write the JSON report to, only with --format json. If not specified the report is printed on the standard output.
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    /// File receiving every path which could not be scanned, with its error number and class separated by tabs.
+    #[arg(long)]
+    pub error_log: Option<String>,
+
+    /// File where the state of the scan is saved periodically and when it's interrupted, removed once the scan is complete.
+    #[arg(long)]
+    pub checkpoint: Option<String>,
+
+    /// Number of seconds between two checkpoints. At each checkpoint the workers stop between two entries
+    /// while the file is written.
+    #[arg(long, default_value_t = 300, value_parser = clap::value_parser!(u64).range(1..))]
+    pub checkpoint_interval: u64,
+
+    /// If specified the unfinished scan saved in the --checkpoint file is continued. The other settings must be the same.
+    #[arg(long, requires = "checkpoint")]
+    pub resume: bool,
+
+    /// If specified some additional information are provided.
+    #[arg(short, long)]
+    pub verbose: bool,
+
+    /// Units of the size ranges: decimal (4K = 4000) or binary (4K = 4096).
+    #[arg(short, long, default_value_t = Unit::Decimal)]
+    pub units: Unit,
+
+    /// Upper limits of the size ranges separated by commas, like "4K,8K,1M,1G". Units K, M, G and T follow --units, Ki, Mi, Gi and Ti are always binary.
+    #[arg(short, long, conflicts_with = "buckets_file")]
+    pub buckets: Option<String>,
+
+    /// File listing the upper limits of the size ranges, one per line or separated by commas. Lines starting with # are ignored.
+    #[arg(long)]
+    pub buckets_file: Option<String>,
+
+    /// If specified statx on linux won't be used.
+    #[arg(long)]
+    #[cfg(target_os = "linux")]
+    pub prevent_statx: bool,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// Human readable report on the terminal.
+    Text,
+    /// Full result as a JSON document.
+    Json,
+}
@@ -3,37 +3,31 @@ use std::io::BufRead;
 use std::io::BufReader;
 use std::io::Write;
 
-use crate::objects;
+use fs_scan::objects;
 
 static OUTPUT_FILE: &str = "fs-scan_output.csv";
-static FILE_FIRST_LINE: &str = "Path,Duration_ms,Files,Directories,Empty_files,Less_than_4K,4K_8K,8K_16K,16K_32K,32K_64K,64K_128K,128K_256K,256K_512K,512K_1M,1M_10M,10M_100M,100M_1G,1G";
 
 pub fn save(res: &objects::Result) {
-    match check_file() {
+    match check_file(&res.csv_header()) {
         Err(s) => {
-            println!("ERROR on check: {s}");
+            eprintln!("ERROR on check: {s}");
             return;
         }
-        Ok(s) => println!("SUCCESS on check: {s}"),
+        Ok(s) => eprintln!("SUCCESS on check: {s}"),
     }
 
-    let mut file = OpenOptions::new()
-        .write(true)
-        .append(true)
-        .open(OUTPUT_FILE)
-        .unwrap();
+    let mut file = OpenOptions::new().append(true).open(OUTPUT_FILE).unwrap();
 
     let _ = writeln!(file, "{}", res.csv_line());
 }
 
-fn check_file() -> Result<String, String> {
+fn check_file(first_line: &str) -> Result<String, String> {
     // Open the file
     let mut file = match OpenOptions::new().read(true).write(true).open(OUTPUT_FILE) {
         Err(_) => {
             // File not opened
             // Try to create it
             let file = match OpenOptions::new()
-                .write(true)
                 .read(true)
                 .append(true)
                 .create(true)
@@ -50,7 +44,7 @@ fn check_file() -> Result<String, String> {
     match file.metadata() {
         Ok(m) => {
             if m.len() == 0 {
-                if writeln!(&mut file, "{}", &String::from(FILE_FIRST_LINE)).is_err() {
+                if writeln!(&mut file, "{}", first_line).is_err() {
                     return Err("can't write first line".to_string());
                 }
                 return Ok("new file created and first line added successfully".to_string());
@@ -68,7 +62,7 @@ fn check_file() -> Result<String, String> {
             Ok(l) => l,
             Err(e) => return Err(format!("can't read line: {e}")),
         };
-        if l != FILE_FIRST_LINE {
+        if l != first_line {
             return Err(format!("Not the same line: content {l}"));
         }
     }
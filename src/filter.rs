@@ -0,0 +1,115 @@
+use glob::{MatchOptions, Pattern};
+
+use std::path::Path;
+
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// Glob patterns used to prune entries during the scan.
+///
+/// A pattern containing a `/` is matched against the full path of the entry, like "/lustre/*/scratch".
+/// Otherwise it's matched against the entry name only, like ".snapshot" or "*.tmp".
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    exclude: Vec<Pattern>,
+    include: Vec<Pattern>,
+}
+
+impl Filter {
+    /// Entries matching the pattern are skipped. Excluded directories are not opened.
+    pub fn exclude(&mut self, pattern: &str) -> Result<(), String> {
+        self.exclude.push(parse_pattern(pattern)?);
+        Ok(())
+    }
+
+    /// If include patterns are given, only the entries other than directories matching one of them are counted.
+    pub fn include(&mut self, pattern: &str) -> Result<(), String> {
+        self.include.push(parse_pattern(pattern)?);
+        Ok(())
+    }
+
+    /// Add the exclude patterns listed in a file content, one per line.
+    /// Empty lines and lines starting with `#` are ignored.
+    pub fn exclude_from(&mut self, content: &str) -> Result<(), String> {
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            self.exclude(line)?;
+        }
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.exclude.is_empty() && self.include.is_empty()
+    }
+
+    /// True if the entry must be skipped. Include patterns don't apply to directories.
+    pub fn prunes(&self, path: &Path, is_dir: bool) -> bool {
+        if matches_any(&self.exclude, path) {
+            return true;
+        }
+        !is_dir && !self.include.is_empty() && !matches_any(&self.include, path)
+    }
+}
+
+fn parse_pattern(pattern: &str) -> Result<Pattern, String> {
+    Pattern::new(pattern).map_err(|e| format!("invalid pattern \"{pattern}\": {e}"))
+}
+
+fn matches_any(patterns: &[Pattern], path: &Path) -> bool {
+    let name = match path.file_name() {
+        Some(name) => name.to_string_lossy(),
+        None => return false,
+    };
+    let full = path.to_string_lossy();
+
+    patterns.iter().any(|pattern| {
+        if pattern.as_str().contains('/') {
+            pattern.matches_with(&full, MATCH_OPTIONS)
+        } else {
+            pattern.matches_with(&name, MATCH_OPTIONS)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prunes_by_name_and_full_path() {
+        let mut filter = Filter::default();
+        filter.exclude("*.tmp").unwrap();
+        filter.exclude("/lustre/*/scratch").unwrap();
+
+        assert!(filter.prunes(Path::new("/lustre/a/b/file.tmp"), false));
+        assert!(filter.prunes(Path::new("/lustre/user/scratch"), true));
+        // The wildcard doesn't match a separator
+        assert!(!filter.prunes(Path::new("/lustre/user/x/scratch"), true));
+        assert!(!filter.prunes(Path::new("/lustre/a/file.txt"), false));
+    }
+
+    #[test]
+    fn include_skips_the_other_files_only() {
+        let mut filter = Filter::default();
+        filter.include("*.h5").unwrap();
+
+        assert!(!filter.prunes(Path::new("/lustre/run/out.h5"), false));
+        assert!(filter.prunes(Path::new("/lustre/run/out.log"), false));
+        assert!(!filter.prunes(Path::new("/lustre/run"), true));
+    }
+
+    #[test]
+    fn exclude_from_skips_comments() {
+        let mut filter = Filter::default();
+        filter.exclude_from("# snapshots\n.snapshot\n\n").unwrap();
+
+        assert!(filter.prunes(Path::new("/lustre/.snapshot"), true));
+        assert!(!filter.prunes(Path::new("/lustre/# snapshots"), true));
+    }
+}
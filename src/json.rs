@@ -0,0 +1,15 @@
+use std::fs;
+
+use colored::Colorize;
+
+use fs_scan::objects;
+
+pub fn save(res: &objects::Result, output: Option<&str>) {
+    match output {
+        Some(file) => match fs::write(file, res.json() + "\n") {
+            Ok(_) => eprintln!("JSON report saved to {file}"),
+            Err(e) => eprintln!("can't write JSON report to {file}: {}", e.to_string().red()),
+        },
+        None => println!("{}", res.json()),
+    }
+}
@@ -0,0 +1,18 @@
+//! Scan recursively a directory and report the layout of the files from the size standpoint.
+//!
+//! The scan is configured and started with [`Scanner`]:
+//!
+//! ```no_run
+//! let res = fs_scan::Scanner::new("/lustre").max_threads(32).scan();
+//! println!("{} files in {} directories", res.files, res.directories);
+//! ```
+
+pub mod ages;
+pub mod buckets;
+pub mod checkpoint;
+pub mod filter;
+pub mod objects;
+mod pool;
+mod scanner;
+
+pub use scanner::{Resumed, Scanner};
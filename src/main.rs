@@ -1,142 +1,105 @@
+mod config;
 mod csv;
-mod objects;
+mod json;
 
-use objects::Config;
-
-#[cfg(target_os = "linux")]
 use std::fs;
-use std::path::PathBuf;
-use std::sync::mpsc::channel;
-use std::time;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 
-use clap::Parser;
-use indicatif::{HumanDuration, ProgressBar, ProgressStyle};
+use clap::{CommandFactory, Parser};
+use indicatif::HumanDuration;
 
 use colored::Colorize;
 
-fn main() {
-    let mut conf = objects::Config::parse();
-
-    if conf.max_threads == 0 {
-        conf.max_threads = num_cpus::get() * 4;
-    }
-
-    #[cfg(target_os = "linux")]
-    let statx_capable = statx_supported(&conf);
-    #[cfg(target_os = "windows")]
-    let statx_capable = false;
-
-    let mut res = objects::build_result(&conf.path);
+use fs_scan::checkpoint::Checkpoint;
+use fs_scan::filter::Filter;
+use fs_scan::{buckets, objects};
 
-    // build channel
-    let (sender, receiver) = channel();
+/// Exit code when some entries could not be scanned, the report is still produced.
+const INCOMPLETE_EXIT_CODE: i32 = 2;
 
-    let bar = ProgressBar::new(conf.max_threads as u64);
-    bar.set_style(
-        ProgressStyle::default_bar()
-            .template("{elapsed} {bar:.cyan/blue} {pos:>3}/{len:3} {msg}")
-            .unwrap()
-            .progress_chars("##-"),
-    );
+/// Exit code when the scan is interrupted a second time, without any report.
+const FORCED_EXIT_CODE: i32 = 130;
 
-    // Start scanning at the given path
-    conf.handle_dir(
-        &PathBuf::from(&conf.path),
-        sender.clone(),
-        &bar,
-        statx_capable,
-    );
+fn main() {
+    let conf = config::Config::parse();
+    if conf.output.is_some() && conf.format != config::Format::Json {
+        config::Config::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--output is only used with --format json",
+            )
+            .exit();
+    }
 
-    let cloned_sender_again = sender;
-    let mut running_thread = 0;
-    let mut dir_queue = Vec::new();
-
-    let starting_point = time::Instant::now();
-
-    let display_refresh_time = time::Duration::from_millis(250);
-    let mut last_message = time::Instant::now()
-        .checked_sub(display_refresh_time)
-        .expect("to remove some time");
-
-    // Handle responses
-    for received in receiver {
-        //  Limit the display refresh
-        let dur = time::Instant::now().duration_since(last_message);
-        if dur > display_refresh_time {
-            bar.set_message(format!(
-                "files scanned {} and dirs in queue {}",
-                &res.files,
-                &dir_queue.len()
-            ));
-            bar.set_position(running_thread as u64);
+    let limits = match bucket_limits(&conf) {
+        Ok(limits) => limits,
+        Err(e) => {
+            eprintln!("invalid bucket limits: {:}", e.red());
+            process::exit(1);
+        }
+    };
 
-            last_message = time::Instant::now();
+    let filter = match build_filter(&conf) {
+        Ok(filter) => filter,
+        Err(e) => {
+            eprintln!("invalid filter: {:}", e.red());
+            process::exit(1);
         }
+    };
 
-        // Check the type of the given element
-        match received.t {
-            // If Dir
-            objects::ResponseType::Dir => {
-                res.directories += 1;
-                // Check if the number of running thread is not too height
-                if running_thread >= conf.max_threads {
-                    // If it's over four times the number of CPU than the folder is saved into a queue
-                    dir_queue.push(received);
-                } else {
-                    // No problem with too much concurrency, so let's run the scan right away
-                    running_thread += 1;
-
-                    // // Add latency to debug the display
-                    // thread::sleep(time::Duration::from_millis(5));
-
-                    conf.handle_dir(
-                        &received.path,
-                        cloned_sender_again.clone(),
-                        &bar,
-                        statx_capable,
-                    );
-                }
-            }
-            // If this signal a directory scan terminated
-            objects::ResponseType::DoneDir => {
-                // The process is done
-                // Break the loop to display the results
-                if running_thread == 0 {
-                    bar.set_message(format!("Total file scanned {}", &res.files));
-                    break;
-                }
-                match dir_queue.pop() {
-                    Some(dir) => {
-                        // // Add latency to debug the display
-                        // thread::sleep(time::Duration::from_millis(5));
-
-                        conf.handle_dir(
-                            &dir.path,
-                            cloned_sender_again.clone(),
-                            &bar,
-                            statx_capable,
-                        );
-                    }
-                    None => {
-                        running_thread -= 1;
-                    }
-                };
-            }
-            // If File
-            objects::ResponseType::File => {
-                handle_file(received.len, &mut res);
+    let scanner = fs_scan::Scanner::new(conf.path.clone().unwrap_or_default())
+        .filter(filter)
+        .one_file_system(conf.one_file_system)
+        .max_depth(conf.max_depth.map(|d| d as usize))
+        .age(conf.age)
+        .matrix(conf.matrix)
+        .owners(conf.owners)
+        .top_extensions(conf.top_extensions)
+        .fold_case(conf.fold_case)
+        .error_log(conf.error_log.as_ref().map(PathBuf::from))
+        .stop_flag(stop_on_signal())
+        .checkpoint(conf.checkpoint.as_ref().map(PathBuf::from))
+        .checkpoint_interval(Duration::from_secs(conf.checkpoint_interval))
+        .unit(conf.units)
+        .buckets(limits)
+        .top_dirs(conf.top_dirs)
+        .top_dirs_depth(conf.top_dirs_depth)
+        .largest_files(conf.largest_files)
+        .allocated(conf.allocated)
+        .max_threads(conf.max_threads)
+        .verbose(conf.verbose)
+        .progress(true);
+    #[cfg(target_os = "linux")]
+    let scanner = scanner.statx(!conf.prevent_statx);
+
+    let res = if conf.resume {
+        match resume(scanner, &conf) {
+            Ok(resumed) => resumed.scan(),
+            Err(e) => {
+                eprintln!("can't resume the scan: {:}", e.red());
+                process::exit(1);
             }
         }
-    }
-    bar.finish();
-
-    // Save the time spend
-    res.duration = starting_point.elapsed();
+    } else {
+        scanner.scan()
+    };
 
     if conf.save_csv {
         csv::save(&res);
     }
 
+    if conf.format == config::Format::Json {
+        json::save(&res, conf.output.as_deref());
+        // Keep the standard output clean when it holds the JSON document
+        if conf.output.is_none() {
+            exit(&res);
+        }
+    }
+
     let ms_dur = res.duration.as_millis();
     let mut duration_to_display = ms_dur.to_string() + "ms";
     if ms_dur > 1000 {
@@ -144,55 +107,202 @@ fn main() {
     }
     println!("Scan took {}", duration_to_display.bold());
 
+    if res.interrupted {
+        println!(
+            "{} -> partial report, {} directories still queued",
+            "Scan interrupted".red().bold(),
+            nice_number(res.queued_dirs)
+        );
+    }
+
     println!("Files -> {}", nice_number(res.files));
+    println!("Total size -> {}", res.unit.human_size(res.bytes).bold());
+    if res.allocated {
+        println!(
+            "Allocated size -> {} (ratio {:.2})",
+            res.unit.human_size(res.allocated_bytes).bold(),
+            res.allocated_ratio()
+        );
+    }
+    if res.extra_links > 0 {
+        println!(
+            "Extra hard links (not counted) -> {}",
+            nice_number(res.extra_links)
+        );
+    }
     println!("Directories -> {}", nice_number(res.directories));
+    if !res.skipped_mounts.is_empty() {
+        println!(
+            "Skipped mount points -> {}",
+            nice_number(res.skipped_mounts.len())
+        );
+        if conf.verbose {
+            for mount in &res.skipped_mounts {
+                println!("  {}", mount.blue());
+            }
+        }
+    }
+    if res.pruned > 0 {
+        println!("Pruned entries -> {}", nice_number(res.pruned));
+    }
+    if res.non_utf8_names > 0 {
+        println!("Non UTF-8 names -> {}", nice_number(res.non_utf8_names));
+    }
+    for kind in objects::EntryKind::ALL {
+        // Regular files are already displayed as files
+        if kind != objects::EntryKind::Regular && res.entries(kind) > 0 {
+            println!("{} -> {}", kind.label(), nice_number(res.entries(kind)));
+        }
+    }
     println!("Empty files -> {}", nice_number(res.empty_file));
-    println!("Less than 4K -> {}", nice_number(res.less_than_4_k));
-    println!(
-        "Between 4KB and 8KB -> {}",
-        nice_number(res.between_4_k_8_k)
-    );
-    println!(
-        "Between 8KB and 16KB -> {}",
-        nice_number(res.between_8_k_16_k)
-    );
-    println!(
-        "Between 16KB and 32KB -> {}",
-        nice_number(res.between_16_k_32_k)
-    );
-    println!(
-        "Between 32KB and 64KB -> {}",
-        nice_number(res.between_32_k_64_k)
-    );
-    println!(
-        "Between 64KB and 128KB -> {}",
-        nice_number(res.between_64_k_128_k)
-    );
-    println!(
-        "Between 128KB and 256KB -> {}",
-        nice_number(res.between_128_k_256_k)
-    );
-    println!(
-        "Between 256KB and 512KB -> {}",
-        nice_number(res.between_256_k_512_k)
-    );
-    println!(
-        "Between 512KB and 1MB -> {}",
-        nice_number(res.between_512_k_1_m)
-    );
-    println!(
-        "Between 1MB and 10MB -> {}",
-        nice_number(res.between_1_m_10_m)
+    for bucket in &res.buckets {
+        let mut allocated = String::new();
+        if res.allocated {
+            allocated = format!(
+                " | allocated {}",
+                res.unit.human_size(bucket.allocated_bytes).bold()
+            );
+        }
+        println!(
+            "{} -> {} | {} ({:.1}%){allocated}",
+            bucket.label(res.unit),
+            nice_number(bucket.files),
+            res.unit.human_size(bucket.bytes).bold(),
+            res.percent_of_bytes(bucket.bytes)
+        );
+    }
+
+    if let Some(field) = res.age_field {
+        println!("Age from {field}:");
+        for age in &res.ages {
+            println!(
+                "  {} -> {} | {} ({:.1}%)",
+                age.label(),
+                nice_number(age.files),
+                res.unit.human_size(age.bytes).bold(),
+                res.percent_of_bytes(age.bytes)
+            );
+        }
+        if res.unknown_age > 0 {
+            println!("  Unknown -> {}", nice_number(res.unknown_age));
+        }
+        if !res.matrix.is_empty() {
+            print_matrix("Files per size and age", &res, |c| c.files.to_string());
+            print_matrix("Bytes per size and age", &res, |c| {
+                res.unit.human_size(c.bytes)
+            });
+        }
+    }
+
+    if res.owners {
+        print_owners("Users", &res.users, &res);
+        print_owners("Groups", &res.groups, &res);
+    }
+
+    print_top_extensions(
+        "Top extensions by files",
+        &res.top_extensions_by_files,
+        &res,
     );
-    println!(
-        "Between 10MB and 100MB -> {}",
-        nice_number(res.between_10_m_100_m)
+    print_top_extensions("Top extensions by size", &res.top_extensions_by_bytes, &res);
+
+    if !res.largest_files.is_empty() {
+        println!("Largest files:");
+        for file in &res.largest_files {
+            println!(
+                "  {} -> {}",
+                res.unit.human_size(file.size).bold(),
+                file.path.blue()
+            );
+        }
+    }
+
+    if conf.depth_report {
+        println!("Per depth:");
+        for (depth, d) in res.depths.iter().enumerate().skip(1) {
+            println!(
+                "  {depth} -> files {} | {} | directories {}",
+                nice_number(d.files),
+                res.unit.human_size(d.bytes).bold(),
+                nice_number(d.directories)
+            );
+        }
+    }
+
+    print_top_dirs("Top directories by files", &res.top_dirs_by_files, &res);
+    print_top_dirs("Top directories by size", &res.top_dirs_by_bytes, &res);
+
+    print_errors(&res, conf.error_log.as_deref());
+    exit(&res);
+}
+
+/// Exit with a non zero code if the scan was incomplete.
+fn exit(res: &objects::Result) -> ! {
+    if res.incomplete() {
+        process::exit(INCOMPLETE_EXIT_CODE);
+    }
+    process::exit(0);
+}
+
+/// Continue the scan saved in the checkpoint file.
+fn resume(scanner: fs_scan::Scanner, conf: &config::Config) -> Result<fs_scan::Resumed, String> {
+    let file = conf.checkpoint.as_deref().unwrap_or_default();
+    let checkpoint = Checkpoint::load(Path::new(file))?;
+    if let Some(path) = &conf.path {
+        if path != checkpoint.path() {
+            return Err(format!(
+                "the checkpoint is for {:?}, not {:?}",
+                checkpoint.path(),
+                path
+            ));
+        }
+    }
+    let path = checkpoint.path().to_string_lossy().to_string();
+    let pending = checkpoint.pending_dirs();
+    let resumed = scanner.resume(checkpoint)?;
+    eprintln!(
+        "Resuming the scan of {} with {} directories left",
+        path.blue(),
+        nice_number(pending)
     );
+    Ok(resumed)
+}
+
+/// Stop the scan on the first SIGINT or SIGTERM to get a partial report, exit right away on the second one.
+fn stop_on_signal() -> Arc<AtomicBool> {
+    let stop = Arc::new(AtomicBool::new(false));
+    let flag = Arc::clone(&stop);
+    let handler = ctrlc::set_handler(move || {
+        if flag.swap(true, Ordering::Relaxed) {
+            process::exit(FORCED_EXIT_CODE);
+        }
+    });
+    if let Err(e) = handler {
+        eprintln!(
+            "can't handle the interruptions, no partial report will be given: {:}",
+            e.to_string().red()
+        );
+    }
+    stop
+}
+
+fn print_errors(res: &objects::Result, error_log: Option<&str>) {
+    if res.error_count() == 0 {
+        println!("Errors -> {}", "none".green());
+        return;
+    }
     println!(
-        "Between 100MB and 1GB -> {}",
-        nice_number(res.between_100_m_1_g)
+        "Errors -> {} entries could not be scanned, the report is incomplete",
+        nice_number(res.error_count()).red()
     );
-    println!("More than 1GB -> {}", nice_number(res.more_than_1_g));
+    for kind in objects::ErrorKind::ALL {
+        if res.errors(kind) > 0 {
+            println!("  {} -> {}", kind.label(), nice_number(res.errors(kind)));
+        }
+    }
+    if let Some(file) = error_log {
+        println!("  Failing paths written to {}", file.blue());
+    }
 }
 
 fn nice_number(input: usize) -> colored::ColoredString {
@@ -205,179 +315,125 @@ fn nice_number(input: usize) -> colored::ColoredString {
     }
 }
 
-fn handle_file(len: u64, res: &mut objects::Result) {
-    if len == 0 {
-        res.empty_file += 1;
-    } else if len < 4_000 {
-        res.less_than_4_k += 1;
-    } else if len < 8_000 {
-        res.between_4_k_8_k += 1;
-    } else if len < 16_000 {
-        res.between_8_k_16_k += 1;
-    } else if len < 32_000 {
-        res.between_16_k_32_k += 1;
-    } else if len < 64_000 {
-        res.between_32_k_64_k += 1;
-    } else if len < 128_000 {
-        res.between_64_k_128_k += 1;
-    } else if len < 256_000 {
-        res.between_128_k_256_k += 1;
-    } else if len < 512_000 {
-        res.between_256_k_512_k += 1;
-    } else if len < 1_000_000 {
-        res.between_512_k_1_m += 1;
-    } else if len < 10_000_000 {
-        res.between_1_m_10_m += 1;
-    } else if len < 100_000_000 {
-        res.between_10_m_100_m += 1;
-    } else if len < 1_000_000_000 {
-        res.between_100_m_1_g += 1;
-    } else {
-        res.more_than_1_g += 1;
+fn print_matrix<F>(title: &str, res: &objects::Result, cell: F)
+where
+    F: Fn(&objects::MatrixCell) -> String,
+{
+    let labels: Vec<String> = res.buckets.iter().map(|b| b.label(res.unit)).collect();
+    let ages: Vec<String> = res.ages.iter().map(|a| a.short_label()).collect();
+    let cells: Vec<Vec<String>> = res
+        .matrix
+        .iter()
+        .map(|row| row.iter().map(&cell).collect())
+        .collect();
+
+    let label_width = labels.iter().map(String::len).max().unwrap_or(0);
+    let width = ages
+        .iter()
+        .chain(cells.iter().flatten())
+        .map(String::len)
+        .max()
+        .unwrap_or(0);
+
+    println!("{title}:");
+    let mut header = format!("  {:label_width$}", "");
+    for age in &ages {
+        header.push_str(&format!(" | {age:>width$}"));
     }
-    res.files += 1;
-}
-
-#[cfg(target_os = "linux")]
-fn statx_supported(conf: &Config) -> bool {
-    // This is to disable statx manually
-    if conf.prevent_statx {
-        if conf.verbose {
-            println!("{:}", "statx was disabled manually".yellow());
+    println!("{}", header.bold());
+    for (label, row) in labels.iter().zip(&cells) {
+        let mut line = format!("  {label:label_width$}");
+        for value in row {
+            line.push_str(&format!(" | {value:>width$}"));
         }
+        println!("{line}");
+    }
+}
 
-        return false;
+fn print_owners(title: &str, owners: &[objects::OwnerStats], res: &objects::Result) {
+    println!("{title}:");
+    for owner in owners {
+        println!(
+            "  {} -> {} files | {} ({:.1}%) | {} small files",
+            owner.name,
+            nice_number(owner.files),
+            res.unit.human_size(owner.bytes).bold(),
+            res.percent_of_bytes(owner.bytes),
+            nice_number(owner.small_files)
+        );
     }
+}
 
-    let entries = match fs::read_dir(&conf.path) {
-        Ok(entries) => entries,
-        Err(e) => {
+fn print_top_extensions(
+    title: &str,
+    extensions: &[objects::ExtensionStats],
+    res: &objects::Result,
+) {
+    if extensions.is_empty() {
+        return;
+    }
+    println!("{title}:");
+    for extension in extensions {
+        println!(
+            "  {} -> {} | {} ({:.1}%)",
+            extension.label().blue(),
+            nice_number(extension.files),
+            res.unit.human_size(extension.bytes).bold(),
+            res.percent_of_bytes(extension.bytes)
+        );
+        // Only the size ranges holding files are displayed to keep the report short
+        for bucket in extension.buckets.iter().filter(|b| b.files > 0) {
             println!(
-                "the path can't be read as a directory: {:}",
-                e.to_string().red()
+                "    {} -> {} | {}",
+                bucket.label(res.unit),
+                nice_number(bucket.files),
+                res.unit.human_size(bucket.bytes)
             );
-            return false;
-        }
-    };
-
-    // Get the fist file from the directory
-    for entry in entries {
-        match entry {
-            Ok(entry) => match entry.file_type() {
-                Ok(t) => {
-                    if t.is_dir() {
-                        continue;
-                    } else if t.is_file() {
-                        return test_statx_on_file(&conf, entry);
-                    }
-                }
-                Err(e) => {
-                    println!(
-                        "can't get type of file {:?} with error: {:}",
-                        entry.file_name().as_os_str(),
-                        e.to_string().red()
-                    );
-                    continue;
-                }
-            },
-            Err(e) => {
-                println!(
-                    "can't get the content from the directory: {:}",
-                    e.to_string().red()
-                );
-            }
         }
     }
-
-    false
 }
 
-#[cfg(target_os = "linux")]
-fn test_statx_on_file(conf: &Config, entry: fs::DirEntry) -> bool {
-    use rustix::fs::{cwd, openat, statx, AtFlags, Mode, OFlags, StatxFlags};
-    use std::ffi::CString;
-
-    let return_false = |message: String, verbose| -> bool {
-        // If verbose it mention the fact that Statx is not supported on this system
-        if verbose {
-            // Print the given message
-            println!("{message:}");
-            println!("statx is {:} supported on this system", "NOT".red());
-        }
-
-        // Return false
-        false
-    };
-
-    // Generate a CString for the directory
-    let dir_c_str = match CString::new(conf.path.as_str()) {
-        Ok(cs) => cs,
-        Err(e) => {
-            return return_false(
-                format!(
-                    "can't make the directory C string: {:}",
-                    e.to_string().red()
-                ),
-                conf.verbose,
-            );
-        }
-    };
-
-    // Open the directory from the CString directory
-    let dir = match openat(
-        cwd(),
-        &dir_c_str,
-        OFlags::RDONLY | OFlags::DIRECTORY,
-        Mode::empty(),
-    ) {
-        Ok(d) => d,
-        Err(e) => {
-            return return_false(
-                format!("can't open file with statx lib: {:}", e.to_string().red()),
-                conf.verbose,
-            );
-        }
-    };
-
-    // Generate a CString for the file
-    let file_c_str = match CString::new(match entry.file_name().to_str() {
-        Some(s) => s,
-        None => {
-            return return_false(format!("can't get entry file name"), conf.verbose);
-        }
-    }) {
-        Ok(cs) => cs,
-        Err(e) => {
-            return return_false(
-                format!(
-                    "can't make CString from file name: {:}",
-                    e.to_string().red()
-                ),
-                conf.verbose,
-            );
-        }
-    };
+fn print_top_dirs(title: &str, dirs: &[objects::DirStats], res: &objects::Result) {
+    if dirs.is_empty() {
+        return;
+    }
+    println!("{title}:");
+    for dir in dirs {
+        println!(
+            "  {} | {} -> {}",
+            nice_number(dir.files),
+            res.unit.human_size(dir.bytes).bold(),
+            dir.path.blue()
+        );
+    }
+}
 
-    // Extract statx based on the previously generated dir and file path via CString
-    match statx(
-        &dir,
-        &file_c_str,
-        AtFlags::SYMLINK_NOFOLLOW | AtFlags::STATX_DONT_SYNC,
-        StatxFlags::SIZE | StatxFlags::TYPE,
-    ) {
-        Ok(stat) => stat,
-        Err(e) => {
-            return return_false(
-                format!("can't get stat with statx: {:}", e.to_string().red()),
-                conf.verbose,
-            );
+fn build_filter(conf: &config::Config) -> Result<Filter, String> {
+    let mut filter = Filter::default();
+    for pattern in &conf.exclude {
+        filter.exclude(pattern)?;
+    }
+    if let Some(file) = &conf.exclude_from {
+        match fs::read_to_string(file) {
+            Ok(content) => filter.exclude_from(&content)?,
+            Err(e) => return Err(format!("can't read {file}: {e}")),
         }
-    };
-
-    //Display to user that statx is supported on the system
-    if conf.verbose {
-        println!("statx is {:} on this system", "supported".green());
     }
+    for pattern in &conf.include {
+        filter.include(pattern)?;
+    }
+    Ok(filter)
+}
 
-    true
+fn bucket_limits(conf: &config::Config) -> Result<Vec<u64>, String> {
+    if let Some(list) = &conf.buckets {
+        return buckets::parse_limits(list, conf.units);
+    }
+    if let Some(file) = &conf.buckets_file {
+        return match fs::read_to_string(file) {
+            Ok(content) => buckets::parse_limits(&content, conf.units),
+            Err(e) => Err(format!("can't read {file}: {e}")),
+        };
+    }
+    Ok(conf.units.default_limits())
 }
@@ -1,408 +1,971 @@
-use colored::Colorize;
-use indicatif::ProgressBar;
+#[cfg(target_os = "linux")]
+use std::ffi::OsString;
+use std::io;
+use std::path::{Path, PathBuf};
+#[cfg(target_os = "linux")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(target_os = "linux")]
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 
-use std::fs::{self, ReadDir};
-use std::path::PathBuf;
-use std::sync::mpsc::Sender;
-use std::thread;
-use std::time::Duration;
+use crate::ages::{age, age_index, AgeBucket, TimeField, DEFAULT_AGE_LIMITS};
+use crate::buckets::{bucket_index, build_buckets, Bucket, Unit};
 
+/// Outcome of a scan, the number of files and their total size are given per size range.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Result {
-    pub path: String,
+    /// Scanned path, only converted to a string in the reports.
+    #[serde(with = "crate::checkpoint::raw_path")]
+    pub path: PathBuf,
+    pub start_time: SystemTime,
     pub duration: Duration,
+    pub statx: bool,
     pub files: usize,
+    pub bytes: u64,
+    /// True if the allocated size on disk was collected.
+    pub allocated: bool,
+    pub allocated_bytes: u64,
+    /// Names of files with several hard links seen after the first one, they are not counted.
+    pub extra_links: usize,
+    /// Number of entries per kind, in the order of [`EntryKind::ALL`].
+    pub entry_kinds: [usize; EntryKind::ALL.len()],
+    /// Number of entries skipped because of the exclude and include patterns.
+    pub pruned: usize,
+    /// Number of entries whose name is not valid UTF-8. They are scanned and shown lossily in the reports.
+    pub non_utf8_names: usize,
+    /// Number of errors per class, in the order of [`ErrorKind::ALL`]. The failing entries are not counted.
+    pub errors: [usize; ErrorKind::ALL.len()],
+    /// True if the scan was stopped before its end, the totals only cover the directories scanned.
+    pub interrupted: bool,
+    /// Directories found but not scanned because the scan was stopped.
+    pub queued_dirs: usize,
+    /// Directories not scanned because they are on another filesystem.
+    pub skipped_mounts: Vec<String>,
     pub directories: usize,
+    /// Totals per depth, the entries of the scanned path being at depth 1.
+    pub depths: Vec<DepthStats>,
+    /// Timestamp used for the age ranges, if collected.
+    pub age_field: Option<TimeField>,
+    pub ages: Vec<AgeBucket>,
+    /// Files for which the timestamp is not available.
+    pub unknown_age: usize,
+    /// Non empty files with a known age per size range and age range, indexed as `matrix[size][age]`.
+    /// Empty if the matrix was not requested.
+    pub matrix: Vec<Vec<MatrixCell>>,
+    /// True if the owners of the files were collected.
+    pub owners: bool,
+    /// Totals per user owning the files, the biggest first.
+    pub users: Vec<OwnerStats>,
+    /// Totals per group owning the files, the biggest first.
+    pub groups: Vec<OwnerStats>,
+    pub top_extensions_by_files: Vec<ExtensionStats>,
+    pub top_extensions_by_bytes: Vec<ExtensionStats>,
     pub empty_file: usize,
-    pub less_than_4_k: usize,
-    pub between_4_k_8_k: usize,
-    pub between_8_k_16_k: usize,
-    pub between_16_k_32_k: usize,
-    pub between_32_k_64_k: usize,
-    pub between_64_k_128_k: usize,
-    pub between_128_k_256_k: usize,
-    pub between_256_k_512_k: usize,
-    pub between_512_k_1_m: usize,
-    pub between_1_m_10_m: usize,
-    pub between_10_m_100_m: usize,
-    pub between_100_m_1_g: usize,
-    pub more_than_1_g: usize,
+    pub unit: Unit,
+    pub buckets: Vec<Bucket>,
+    pub top_dirs_by_files: Vec<DirStats>,
+    pub top_dirs_by_bytes: Vec<DirStats>,
+    /// Largest files, the biggest first.
+    pub largest_files: Vec<FileSize>,
 }
-pub fn build_result(path: &str) -> Result {
+pub fn build_result(path: &Path, limits: &[u64], unit: Unit) -> Result {
     Result {
-        path: path.to_string(),
+        path: path.to_path_buf(),
 
+        start_time: SystemTime::now(),
         duration: Duration::new(0, 0),
+        statx: false,
 
         files: 0,
+        bytes: 0,
+        allocated: false,
+        allocated_bytes: 0,
+        extra_links: 0,
+        entry_kinds: [0; EntryKind::ALL.len()],
+        pruned: 0,
+        non_utf8_names: 0,
+        errors: [0; ErrorKind::ALL.len()],
+        interrupted: false,
+        queued_dirs: 0,
+        skipped_mounts: Vec::new(),
         directories: 0,
+        depths: Vec::new(),
+        age_field: None,
+        ages: Vec::new(),
+        unknown_age: 0,
+        matrix: Vec::new(),
+        owners: false,
+        users: Vec::new(),
+        groups: Vec::new(),
+        top_extensions_by_files: Vec::new(),
+        top_extensions_by_bytes: Vec::new(),
 
         empty_file: 0,
-        less_than_4_k: 0,
-        between_4_k_8_k: 0,
-        between_8_k_16_k: 0,
-        between_16_k_32_k: 0,
-        between_32_k_64_k: 0,
-        between_64_k_128_k: 0,
-        between_128_k_256_k: 0,
-        between_256_k_512_k: 0,
-        between_512_k_1_m: 0,
-        between_1_m_10_m: 0,
-        between_10_m_100_m: 0,
-        between_100_m_1_g: 0,
-        more_than_1_g: 0,
+        unit,
+        buckets: build_buckets(limits),
+        top_dirs_by_files: Vec::new(),
+        top_dirs_by_bytes: Vec::new(),
+        largest_files: Vec::new(),
     }
 }
 
 impl Result {
+    /// Account a file of the given apparent and allocated sizes in bytes,
+    /// the limits being the ones of the size ranges.
+    pub fn add_file(&mut self, len: u64, allocated: u64, limits: &[u64]) {
+        if len == 0 {
+            self.empty_file += 1;
+        } else {
+            let i = bucket_index(limits, len);
+            self.buckets[i].files += 1;
+            self.buckets[i].bytes += len;
+            self.buckets[i].allocated_bytes += allocated;
+        }
+        self.files += 1;
+        self.bytes += len;
+        self.allocated_bytes += allocated;
+        self.add_entry(EntryKind::Regular);
+    }
+
+    /// Account a file in the age ranges, the times being in seconds since the epoch.
+    /// The age ranges are the ones of [`DEFAULT_AGE_LIMITS`], the limits are the ones of the size ranges.
+    pub fn add_age(&mut self, now: i64, time: Option<i64>, len: u64, limits: &[u64]) {
+        let time = match time {
+            Some(time) => time,
+            None => {
+                self.unknown_age += 1;
+                return;
+            }
+        };
+        let i = age_index(&DEFAULT_AGE_LIMITS, age(now, time));
+        self.ages[i].files += 1;
+        self.ages[i].bytes += len;
+
+        if !self.matrix.is_empty() && len > 0 {
+            let s = bucket_index(limits, len);
+            self.matrix[s][i].files += 1;
+            self.matrix[s][i].bytes += len;
+        }
+    }
+
+    /// Add the counters and the size, age and depth ranges of another result built with the same settings.
+    /// The ranked lists, like the top directories, the owners or the largest files, are not merged
+    /// since they are built from the complete totals at the end of the scan.
+    pub fn merge(&mut self, other: &Result) {
+        self.files += other.files;
+        self.bytes += other.bytes;
+        self.allocated_bytes += other.allocated_bytes;
+        self.extra_links += other.extra_links;
+        for (count, other_count) in self.entry_kinds.iter_mut().zip(&other.entry_kinds) {
+            *count += other_count;
+        }
+        self.pruned += other.pruned;
+        self.skipped_mounts
+            .extend(other.skipped_mounts.iter().cloned());
+        self.non_utf8_names += other.non_utf8_names;
+        for (count, other_count) in self.errors.iter_mut().zip(&other.errors) {
+            *count += other_count;
+        }
+        self.queued_dirs += other.queued_dirs;
+        self.directories += other.directories;
+        if self.depths.len() < other.depths.len() {
+            self.depths
+                .resize(other.depths.len(), DepthStats::default());
+        }
+        for (depth, other_depth) in self.depths.iter_mut().zip(&other.depths) {
+            depth.files += other_depth.files;
+            depth.bytes += other_depth.bytes;
+            depth.directories += other_depth.directories;
+        }
+        for (age, other_age) in self.ages.iter_mut().zip(&other.ages) {
+            age.files += other_age.files;
+            age.bytes += other_age.bytes;
+        }
+        self.unknown_age += other.unknown_age;
+        for (row, other_row) in self.matrix.iter_mut().zip(&other.matrix) {
+            for (cell, other_cell) in row.iter_mut().zip(other_row) {
+                cell.files += other_cell.files;
+                cell.bytes += other_cell.bytes;
+            }
+        }
+        self.empty_file += other.empty_file;
+        for (bucket, other_bucket) in self.buckets.iter_mut().zip(&other.buckets) {
+            bucket.files += other_bucket.files;
+            bucket.bytes += other_bucket.bytes;
+            bucket.allocated_bytes += other_bucket.allocated_bytes;
+        }
+    }
+
+    /// Totals of the entries at the given depth.
+    pub fn depth_mut(&mut self, depth: usize) -> &mut DepthStats {
+        if self.depths.len() <= depth {
+            self.depths.resize(depth + 1, DepthStats::default());
+        }
+        &mut self.depths[depth]
+    }
+
+    /// Count an entry of the given kind.
+    pub fn add_entry(&mut self, kind: EntryKind) {
+        self.entry_kinds[kind as usize] += 1;
+    }
+
+    /// Number of entries of the given kind.
+    pub fn entries(&self, kind: EntryKind) -> usize {
+        self.entry_kinds[kind as usize]
+    }
+
+    /// Count an error of the given class.
+    pub fn add_error(&mut self, kind: ErrorKind) {
+        self.errors[kind as usize] += 1;
+    }
+
+    /// Number of errors of the given class.
+    pub fn errors(&self, kind: ErrorKind) -> usize {
+        self.errors[kind as usize]
+    }
+
+    /// Total number of errors.
+    pub fn error_count(&self) -> usize {
+        self.errors.iter().sum()
+    }
+
+    /// True if some entries could not be scanned, because of errors or an interruption.
+    pub fn incomplete(&self) -> bool {
+        self.interrupted || self.error_count() > 0
+    }
+
+    /// Allocated size on disk divided by the apparent size.
+    pub fn allocated_ratio(&self) -> f64 {
+        if self.bytes == 0 {
+            return 0.0;
+        }
+        self.allocated_bytes as f64 / self.bytes as f64
+    }
+
+    /// Share of the total size in percent for the given number of bytes.
+    pub fn percent_of_bytes(&self, bytes: u64) -> f64 {
+        if self.bytes == 0 {
+            return 0.0;
+        }
+        bytes as f64 * 100.0 / self.bytes as f64
+    }
+
+    pub fn csv_header(&self) -> String {
+        let mut header = "Path,Duration_ms,Files,Directories,Empty_files".to_string();
+        for bucket in &self.buckets {
+            header.push(',');
+            header.push_str(&bucket.csv_name(self.unit));
+        }
+        header.push_str(",Bytes");
+        for bucket in &self.buckets {
+            header.push(',');
+            header.push_str(&bucket.csv_name(self.unit));
+            header.push_str("_bytes");
+        }
+        if self.allocated {
+            header.push_str(",Allocated_bytes");
+            for bucket in &self.buckets {
+                header.push(',');
+                header.push_str(&bucket.csv_name(self.unit));
+                header.push_str("_allocated");
+            }
+        }
+        if let Some(field) = self.age_field {
+            for age in &self.ages {
+                header.push_str(&format!(",{field}_{}", age.csv_name()));
+            }
+            for age in &self.ages {
+                header.push_str(&format!(",{field}_{}_bytes", age.csv_name()));
+            }
+            header.push_str(&format!(",{field}_unknown"));
+            if !self.matrix.is_empty() {
+                for suffix in ["", "_bytes"] {
+                    for bucket in &self.buckets {
+                        for age in &self.ages {
+                            header.push_str(&format!(
+                                ",{}_{field}_{}{suffix}",
+                                bucket.csv_name(self.unit),
+                                age.csv_name()
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+        header.push_str(",Interrupted,Queued_dirs");
+        header
+    }
+
     pub fn csv_line(&self) -> String {
-        format!(
-            "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
-            &self.path,
+        let mut line = format!(
+            "{},{},{},{},{}",
+            &self.path.to_string_lossy(),
             &self.duration.as_millis(),
             &self.files,
             &self.directories,
             &self.empty_file,
-            &self.less_than_4_k,
-            &self.between_4_k_8_k,
-            &self.between_8_k_16_k,
-            &self.between_16_k_32_k,
-            &self.between_32_k_64_k,
-            &self.between_64_k_128_k,
-            &self.between_128_k_256_k,
-            &self.between_256_k_512_k,
-            &self.between_512_k_1_m,
-            &self.between_1_m_10_m,
-            &self.between_10_m_100_m,
-            &self.between_100_m_1_g,
-            &self.more_than_1_g,
-        )
+        );
+        for bucket in &self.buckets {
+            line.push_str(&format!(",{}", bucket.files));
+        }
+        line.push_str(&format!(",{}", self.bytes));
+        for bucket in &self.buckets {
+            line.push_str(&format!(",{}", bucket.bytes));
+        }
+        if self.allocated {
+            line.push_str(&format!(",{}", self.allocated_bytes));
+            for bucket in &self.buckets {
+                line.push_str(&format!(",{}", bucket.allocated_bytes));
+            }
+        }
+        if self.age_field.is_some() {
+            for age in &self.ages {
+                line.push_str(&format!(",{}", age.files));
+            }
+            for age in &self.ages {
+                line.push_str(&format!(",{}", age.bytes));
+            }
+            line.push_str(&format!(",{}", self.unknown_age));
+            for row in &self.matrix {
+                for cell in row {
+                    line.push_str(&format!(",{}", cell.files));
+                }
+            }
+            for row in &self.matrix {
+                for cell in row {
+                    line.push_str(&format!(",{}", cell.bytes));
+                }
+            }
+        }
+        line.push_str(&format!(",{},{}", self.interrupted, self.queued_dirs));
+        line
+    }
+
+    /// Full result as a pretty printed JSON document.
+    pub fn json(&self) -> String {
+        let buckets: Vec<Value> = self
+            .buckets
+            .iter()
+            .map(|b| {
+                json!({
+                    "label": b.label(self.unit),
+                    "min": b.min,
+                    "max": b.max,
+                    "files": b.files,
+                    "bytes": b.bytes,
+                    "allocated_bytes": self.allocated.then_some(b.allocated_bytes),
+                })
+            })
+            .collect();
+
+        let doc = json!({
+            "version": env!("CARGO_PKG_VERSION"),
+            "path": self.path.to_string_lossy(),
+            "start_time": format_time(self.start_time),
+            "duration_ms": self.duration.as_millis() as u64,
+            "statx": self.statx,
+            "unit": self.unit.to_string(),
+            "files": self.files,
+            "bytes": self.bytes,
+            "allocated_bytes": self.allocated.then_some(self.allocated_bytes),
+            "allocated_ratio": self.allocated.then(|| self.allocated_ratio()),
+            "extra_links": self.extra_links,
+            "entry_kinds": EntryKind::ALL
+                .iter()
+                .map(|k| (k.name().to_string(), json!(self.entries(*k))))
+                .collect::<serde_json::Map<String, Value>>(),
+            "pruned": self.pruned,
+            "errors": ErrorKind::ALL
+                .iter()
+                .map(|k| (k.name().to_string(), json!(self.errors(*k))))
+                .collect::<serde_json::Map<String, Value>>(),
+            "incomplete": self.incomplete(),
+            "interrupted": self.interrupted,
+            "queued_dirs": self.queued_dirs,
+            "non_utf8_names": self.non_utf8_names,
+            "skipped_mounts": self.skipped_mounts,
+            "directories": self.directories,
+            "depths": self
+                .depths
+                .iter()
+                .enumerate()
+                .skip(1)
+                .map(|(depth, d)| {
+                    json!({
+                        "depth": depth,
+                        "files": d.files,
+                        "bytes": d.bytes,
+                        "directories": d.directories,
+                    })
+                })
+                .collect::<Vec<Value>>(),
+            "empty_files": self.empty_file,
+            "buckets": buckets,
+            "age": self.age_field.map(|field| {
+                json!({
+                    "field": field.to_string(),
+                    "unknown": self.unknown_age,
+                    "buckets": self
+                        .ages
+                        .iter()
+                        .map(|a| {
+                            json!({
+                                "label": a.label(),
+                                "min_seconds": a.min,
+                                "max_seconds": a.max,
+                                "files": a.files,
+                                "bytes": a.bytes,
+                            })
+                        })
+                        .collect::<Vec<Value>>(),
+                })
+            }),
+            "matrix": (!self.matrix.is_empty()).then(|| {
+                json!({
+                    "field": self.age_field.map(|field| field.to_string()),
+                    "sizes": self.buckets.iter().map(|b| b.label(self.unit)).collect::<Vec<String>>(),
+                    "ages": self.ages.iter().map(|a| a.label()).collect::<Vec<String>>(),
+                    "cells": self
+                        .matrix
+                        .iter()
+                        .map(|row| {
+                            row.iter()
+                                .map(|c| json!({ "files": c.files, "bytes": c.bytes }))
+                                .collect::<Vec<Value>>()
+                        })
+                        .collect::<Vec<Vec<Value>>>(),
+                })
+            }),
+            "users": self.owners.then(|| self.users.iter().map(OwnerStats::json).collect::<Vec<Value>>()),
+            "groups": self.owners.then(|| self.groups.iter().map(OwnerStats::json).collect::<Vec<Value>>()),
+            "top_extensions": {
+                "by_files": self.top_extensions_by_files.iter().map(|e| e.json(self.unit)).collect::<Vec<Value>>(),
+                "by_bytes": self.top_extensions_by_bytes.iter().map(|e| e.json(self.unit)).collect::<Vec<Value>>(),
+            },
+            "largest_files": self
+                .largest_files
+                .iter()
+                .map(|f| json!({ "path": f.path, "size": f.size }))
+                .collect::<Vec<Value>>(),
+            "top_directories": {
+                "by_files": self.top_dirs_by_files.iter().map(DirStats::json).collect::<Vec<Value>>(),
+                "by_bytes": self.top_dirs_by_bytes.iter().map(DirStats::json).collect::<Vec<Value>>(),
+            },
+        });
+
+        format!("{doc:#}")
     }
 }
 
-pub enum ResponseType {
-    File,
-    Dir,
-    DoneDir,
+/// Files of a size range and an age range.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct MatrixCell {
+    pub files: usize,
+    pub bytes: u64,
 }
-pub struct ChanResponse {
-    pub t: ResponseType,
-    pub path: PathBuf,
-    pub len: u64,
+
+/// Totals of the files owned by a user or a group.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OwnerStats {
+    pub id: u32,
+    /// Name from the local user or group database, the numeric id if it can't be resolved.
+    pub name: String,
+    pub files: usize,
+    pub bytes: u64,
+    /// Files in the first size range, the empty ones excluded.
+    pub small_files: usize,
 }
-pub fn build_dir_chan(path: PathBuf) -> ChanResponse {
-    ChanResponse {
-        t: ResponseType::Dir,
-        path,
-        len: 0,
+
+impl OwnerStats {
+    /// Account a file of the given size, the limits being the upper limits of the size ranges.
+    pub fn add_file(&mut self, len: u64, limits: &[u64]) {
+        self.files += 1;
+        self.bytes += len;
+        if len > 0 && limits.first().is_none_or(|first| len < *first) {
+            self.small_files += 1;
+        }
     }
-}
-pub fn build_dir_chan_done() -> ChanResponse {
-    ChanResponse {
-        t: ResponseType::DoneDir,
-        path: PathBuf::new(),
-        len: 0,
+
+    pub fn merge(&mut self, other: &OwnerStats) {
+        self.files += other.files;
+        self.bytes += other.bytes;
+        self.small_files += other.small_files;
+    }
+
+    fn json(&self) -> Value {
+        json!({
+            "id": self.id,
+            "name": self.name,
+            "files": self.files,
+            "bytes": self.bytes,
+            "small_files": self.small_files,
+        })
     }
 }
-pub fn build_file_chan(size: u64) -> ChanResponse {
-    ChanResponse {
-        t: ResponseType::File,
-        path: PathBuf::new(),
-        len: size,
+
+/// Longest extension counted on its own. The longer ones, often dates or ids, are grouped together
+/// so they don't fill the memory with one entry per file.
+pub const MAX_EXTENSION_LEN: usize = 16;
+
+/// Extension of the group of the ones longer than [`MAX_EXTENSION_LEN`], no real extension has a dot.
+pub const LONG_EXTENSIONS: &str = ".long";
+
+/// Totals of the files sharing an extension, with their own size ranges.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExtensionStats {
+    /// Extension without the dot, empty for the files without extension.
+    pub extension: String,
+    pub files: usize,
+    pub bytes: u64,
+    pub buckets: Vec<Bucket>,
+}
+
+impl ExtensionStats {
+    pub fn new(extension: String, limits: &[u64]) -> ExtensionStats {
+        ExtensionStats {
+            extension,
+            files: 0,
+            bytes: 0,
+            buckets: build_buckets(limits),
+        }
+    }
+
+    /// Account a file of the given size, the empty files are not in the size ranges.
+    /// The limits are the ones of the size ranges.
+    pub fn add_file(&mut self, len: u64, limits: &[u64]) {
+        self.files += 1;
+        self.bytes += len;
+        if len > 0 {
+            let i = bucket_index(limits, len);
+            self.buckets[i].files += 1;
+            self.buckets[i].bytes += len;
+        }
+    }
+
+    pub fn merge(&mut self, other: &ExtensionStats) {
+        self.files += other.files;
+        self.bytes += other.bytes;
+        for (bucket, other_bucket) in self.buckets.iter_mut().zip(&other.buckets) {
+            bucket.files += other_bucket.files;
+            bucket.bytes += other_bucket.bytes;
+        }
+    }
+
+    /// Label used in the terminal report, like ".json" or "No extension".
+    pub fn label(&self) -> String {
+        if self.extension.is_empty() {
+            "No extension".to_string()
+        } else if self.extension == LONG_EXTENSIONS {
+            format!("Extensions longer than {MAX_EXTENSION_LEN} characters")
+        } else {
+            format!(".{}", self.extension)
+        }
+    }
+
+    fn json(&self, unit: Unit) -> Value {
+        json!({
+            "extension": (!self.extension.is_empty()).then_some(&self.extension),
+            "files": self.files,
+            "bytes": self.bytes,
+            "buckets": self
+                .buckets
+                .iter()
+                .map(|b| json!({ "label": b.label(unit), "files": b.files, "bytes": b.bytes }))
+                .collect::<Vec<Value>>(),
+        })
     }
 }
 
-use clap::Parser;
+/// Totals of the entries at a given depth.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DepthStats {
+    pub files: usize,
+    pub bytes: u64,
+    pub directories: usize,
+}
 
-/// Scan recursively the given directory and generate a report of the scanned files based on their relative size.
-#[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
-pub struct Config {
+/// Totals of the files of a directory and its sub directories.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DirStats {
     pub path: String,
+    pub files: usize,
+    pub bytes: u64,
+    /// Number of non empty files per size range, in the order of [`Result::buckets`].
+    pub buckets: Vec<usize>,
+}
 
-    /// Maximum number of parallel threads. If not configured, 4 times the number of detected logical CPU.
-    #[arg(short = 't', long, default_value_t = 0)]
-    pub max_threads: usize,
-    /// If specified a CSV log file is generated. Multiple run can be done from the same directory to collect outputs from multiple directories in a single file.
-    #[arg(short, long)]
-    pub save_csv: bool,
+impl DirStats {
+    /// Account a file of the given size in bytes, the limits being the ones of the size ranges.
+    pub fn add_file(&mut self, len: u64, limits: &[u64]) {
+        if len != 0 {
+            if self.buckets.is_empty() {
+                self.buckets = vec![0; limits.len() + 1];
+            }
+            self.buckets[bucket_index(limits, len)] += 1;
+        }
+        self.files += 1;
+        self.bytes += len;
+    }
 
-    /// If specified some additional information are provided.
-    #[arg(short, long)]
-    pub verbose: bool,
+    /// Add the totals of an other directory.
+    pub fn merge(&mut self, other: &DirStats) {
+        if self.buckets.len() < other.buckets.len() {
+            self.buckets.resize(other.buckets.len(), 0);
+        }
+        for (count, other_count) in self.buckets.iter_mut().zip(&other.buckets) {
+            *count += other_count;
+        }
+        self.files += other.files;
+        self.bytes += other.bytes;
+    }
 
-    /// If specified statx on linux won't be used.
-    #[arg(long)]
-    #[cfg(target_os = "linux")]
-    pub prevent_statx: bool,
+    fn json(&self) -> Value {
+        json!({
+            "path": self.path,
+            "files": self.files,
+            "bytes": self.bytes,
+            "buckets": self.buckets,
+        })
+    }
 }
 
-fn display_error_and_stop_thread_before_return(
-    bar: &ProgressBar,
-    ch: &Sender<ChanResponse>,
-    message: String,
-    path: String,
-) {
-    bar.println(message);
-    // Notify the end of the thread
-    match ch.send(build_dir_chan_done()) {
-        Ok(_) => {}
-        Err(e) => {
-            bar.println(format!(
-                "Expect channel to be able to send ERR: 5766\n{:}\n{e}",
-                path,
-            ));
+/// Kind of the entries which are not directories. Only regular files are accounted in the size ranges.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    Regular,
+    Symlink,
+    Fifo,
+    Socket,
+    CharDevice,
+    BlockDevice,
+    Unknown,
+}
+
+impl EntryKind {
+    pub const ALL: [EntryKind; 7] = [
+        EntryKind::Regular,
+        EntryKind::Symlink,
+        EntryKind::Fifo,
+        EntryKind::Socket,
+        EntryKind::CharDevice,
+        EntryKind::BlockDevice,
+        EntryKind::Unknown,
+    ];
+
+    /// Label used in the terminal report.
+    pub fn label(&self) -> &'static str {
+        match self {
+            EntryKind::Regular => "Regular files",
+            EntryKind::Symlink => "Symbolic links",
+            EntryKind::Fifo => "FIFOs",
+            EntryKind::Socket => "Sockets",
+            EntryKind::CharDevice => "Character devices",
+            EntryKind::BlockDevice => "Block devices",
+            EntryKind::Unknown => "Unknown entries",
+        }
+    }
+
+    /// Name used in the structured outputs.
+    pub fn name(&self) -> &'static str {
+        match self {
+            EntryKind::Regular => "regular",
+            EntryKind::Symlink => "symlink",
+            EntryKind::Fifo => "fifo",
+            EntryKind::Socket => "socket",
+            EntryKind::CharDevice => "char_device",
+            EntryKind::BlockDevice => "block_device",
+            EntryKind::Unknown => "unknown",
         }
     }
 }
 
-impl Config {
-    pub fn handle_dir(
-        &self,
-        path: &PathBuf,
-        ch: Sender<ChanResponse>,
-        bar: &ProgressBar,
-        statx_capable: bool,
-    ) {
-        let path_as_str = match path.to_str() {
-            Some(s) => s,
-            None => {
-                return display_error_and_stop_thread_before_return(
-                    bar,
-                    &ch,
-                    format!("Expect path to be real string but got error for {path:?}"),
-                    "can't get path".to_string(),
-                );
-            }
-        };
+/// Class of the errors met while scanning.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    PermissionDenied,
+    /// The entry was removed or renamed during the scan.
+    NotFound,
+    /// Stale NFS or Lustre file handle.
+    StaleHandle,
+    Io,
+    InvalidName,
+    Other,
+}
 
-        match fs::read_dir(path) {
-            Ok(entries) => {
-                let bar = bar.clone();
-                let statx_capable = statx_capable;
-                let path_as_str = path_as_str.to_string();
-                let path = path.clone();
-
-                thread::spawn(move || {
-                    if statx_capable {
-                        #[cfg(target_os = "linux")]
-                        statx_scroller(entries, &ch, &bar, path);
-                    } else {
-                        regular_scroller(entries, &ch, &bar, path);
-                    }
-                    // Notify the end of the thread
-                    match ch.send(build_dir_chan_done()) {
-                        Ok(_) => {}
-                        Err(e) => {
-                            bar.println(format!(
-                                "Expect channel to be able to send the signal to tell the directory \"{:}\" is done successfully but got error:\n{e}",
-                                path_as_str,
-                            ));
-                        }
-                    }
-                });
-            }
-            Err(err) => {
-                bar.println(format!(
-                    "Can't read the directory content of {}: {}",
-                    path_as_str.blue(), err.to_string().bold()
-                ));
-                // Notify the end of the thread
-                match ch.send(build_dir_chan_done()) {
-                    Ok(_) => {}
-                    Err(e) => {
-                        bar.println(format!(
-                            "Expect channel to be able to send ERR: 4252\n{:}\n{e}",
-                            path_as_str,
-                        ));
-                    }
-                }
+impl ErrorKind {
+    pub const ALL: [ErrorKind; 6] = [
+        ErrorKind::PermissionDenied,
+        ErrorKind::NotFound,
+        ErrorKind::StaleHandle,
+        ErrorKind::Io,
+        ErrorKind::InvalidName,
+        ErrorKind::Other,
+    ];
+
+    /// Class of an error returned by the system.
+    pub fn from_io(err: &io::Error) -> ErrorKind {
+        #[cfg(unix)]
+        if let Some(errno) = err.raw_os_error() {
+            use rustix::io::Errno;
+            match Errno::from_raw_os_error(errno) {
+                Errno::ACCESS | Errno::PERM => return ErrorKind::PermissionDenied,
+                Errno::NOENT => return ErrorKind::NotFound,
+                Errno::STALE => return ErrorKind::StaleHandle,
+                Errno::IO => return ErrorKind::Io,
+                Errno::NAMETOOLONG | Errno::ILSEQ => return ErrorKind::InvalidName,
+                _ => {}
             }
         }
+        match err.kind() {
+            io::ErrorKind::PermissionDenied => ErrorKind::PermissionDenied,
+            io::ErrorKind::NotFound => ErrorKind::NotFound,
+            io::ErrorKind::InvalidInput => ErrorKind::InvalidName,
+            _ => ErrorKind::Other,
+        }
     }
-}
 
-#[cfg(target_os = "linux")]
-fn statx_scroller(entries: ReadDir, ch: &Sender<ChanResponse>, bar: &ProgressBar, path: PathBuf) {
-    use rustix::fs::{cwd, openat, statx, AtFlags, Mode, OFlags, StatxFlags};
-    use std::ffi::{CString, OsStr};
-    use std::os::unix::ffi::OsStrExt;
-    use std::path::Path;
-
-    let path_as_str = match path.to_str() {
-        Some(s) => s,
-        None => {
-            return display_error_and_stop_thread_before_return(
-                &bar,
-                &ch,
-                format!("Expect path to be real string but got error for {path:?}"),
-                "can't get path".to_string(),
-            );
+    /// Label used in the terminal report.
+    pub fn label(&self) -> &'static str {
+        match self {
+            ErrorKind::PermissionDenied => "Permission denied",
+            ErrorKind::NotFound => "Not found (removed during the scan)",
+            ErrorKind::StaleHandle => "Stale file handle",
+            ErrorKind::Io => "I/O error",
+            ErrorKind::InvalidName => "Invalid or too long name",
+            ErrorKind::Other => "Other error",
         }
-    };
+    }
 
-    let dir_c_str = match CString::new(path_as_str) {
-        Ok(cs) => cs,
-        Err(e) => {
-            return display_error_and_stop_thread_before_return(
-                &bar,
-                &ch,
-                format!("Expect to be able to convert path into CString for {path:?}: {e:?}"),
-                path_as_str.to_string(),
-            );
+    /// Name used in the structured outputs and the error log.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ErrorKind::PermissionDenied => "permission_denied",
+            ErrorKind::NotFound => "not_found",
+            ErrorKind::StaleHandle => "stale_handle",
+            ErrorKind::Io => "io",
+            ErrorKind::InvalidName => "invalid_name",
+            ErrorKind::Other => "other",
         }
-    };
+    }
+}
 
-    let dir = match openat(
-        cwd(),
-        &dir_c_str,
-        OFlags::RDONLY | OFlags::DIRECTORY,
-        Mode::empty(),
-    ) {
-        Ok(d) => d,
-        Err(e) => {
-            return display_error_and_stop_thread_before_return(
-                &bar,
-                &ch,
-                format!(
-                    "Error opening directory \"{:?}\" with error {e:?}",
-                    &path_as_str
-                ),
-                path_as_str.to_string(),
-            );
-        }
+/// A file and its size in bytes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileSize {
+    pub path: String,
+    pub size: u64,
+}
+
+/// Format a time as RFC 3339 in UTC, like "2022-12-24T18:30:00Z".
+fn format_time(time: SystemTime) -> String {
+    let secs = match time.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs() as i64,
+        Err(_) => 0,
     };
+    let (days, rem) = (secs.div_euclid(86_400), secs.rem_euclid(86_400));
 
-    for entry in entries {
-        match entry {
-            Ok(entry) => {
-                match entry.file_type() {
-                    Ok(t) => {
-                        if t.is_dir() {
-                            match ch.send(build_dir_chan(entry.path())) {
-                                Ok(_) => {}
-                                Err(e) => {
-                                    bar.println(
-                            format!(
-                                "Expect channel to be able to send ERR: 6151\n{path_as_str:}/{:?}\n{e:?}", entry.path(),
-                            )
-                        );
-                                }
-                            }
-                            continue;
-                        }
-                    }
-                    Err(e) => {
-                        bar.println(format!(
-                            "Can't get type of file \"{path_as_str:}/{:?}\"\n{e:?}",
-                            entry.path()
-                        ));
-                        continue;
-                    }
-                }
+    // Convert the number of days since 1970-01-01 into a civil date
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
 
-                let file_c_str = match CString::new(match entry.file_name().to_str() {
-                    Some(s) => s,
-                    None => {
-                        bar.println(
-                            format!("Expected file name or {path_as_str:}/{entry:?}").as_str(),
-                        );
-                        return display_error_and_stop_thread_before_return(
-                            &bar,
-                            &ch,
-                            format!("Expected file name or {path_as_str:}/{entry:?}"),
-                            path_as_str.to_string(),
-                        );
-                    }
-                }) {
-                    Ok(cs) => cs,
-                    Err(e) => {
-                        return display_error_and_stop_thread_before_return(
-                            &bar,
-                            &ch,
-                            format!("Expected file name or \"{path_as_str:?}/{entry:?}\" {e:?}"),
-                            path_as_str.to_string(),
-                        );
-                    }
-                };
-
-                let stat = match statx(
-                    &dir,
-                    &file_c_str,
-                    AtFlags::SYMLINK_NOFOLLOW | AtFlags::STATX_DONT_SYNC,
-                    StatxFlags::SIZE | StatxFlags::TYPE,
-                ) {
-                    Ok(stat) => stat,
-                    Err(err) => {
-                        bar.println(format!(
-                            "Failed to stat file \"{:?}\" with error {err:?}",
-                            Path::new(OsStr::from_bytes(dir_c_str.as_bytes()))
-                                .join(Path::new(OsStr::from_bytes(file_c_str.to_bytes())))
-                        ));
-                        continue;
-                    }
-                };
-                match ch.send(build_file_chan(stat.stx_size)) {
-                    Ok(_) => {}
-                    Err(e) => {
-                        bar.println(format!(
-                            "Expect channel to be able to send ERR: 8096\n{:?}\n{e:?}",
-                            entry.path(),
-                        ));
-                        continue;
-                    }
-                }
-            }
-            Err(err) => {
-                bar.println(format!(
-                    "Can't display the entry of directory \"{:?}\" {err}",
-                    path_as_str
-                ));
-            }
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        rem / 3_600,
+        rem % 3_600 / 60,
+        rem % 60
+    )
+}
+
+pub enum ResponseType {
+    Dir,
+    DoneDir,
+    /// A queued directory dropped without being scanned because the scan was stopped.
+    Skipped,
+}
+/// Message sent by the workers, the files are accounted in their own results.
+pub struct ChanResponse {
+    pub t: ResponseType,
+    pub path: PathBuf,
+    /// Depth of the entry, the entries of the scanned path being at depth 1.
+    pub depth: usize,
+    pub stats: Option<DirStats>,
+    /// Open parent directory and name to open the directory without walking its full path.
+    #[cfg(target_os = "linux")]
+    pub parent: Option<ParentDir>,
+}
+/// Open directory shared by its subdirectories, closed once all of them are opened.
+#[cfg(target_os = "linux")]
+pub type ParentDir = (Arc<OpenDir>, OsString);
+
+/// Descriptor of an open directory, counted in the given number of open directories until it's closed.
+#[cfg(target_os = "linux")]
+pub struct OpenDir {
+    fd: rustix::fd::OwnedFd,
+    open_dirs: Arc<AtomicUsize>,
+}
+
+#[cfg(target_os = "linux")]
+impl OpenDir {
+    pub fn new(fd: rustix::fd::OwnedFd, open_dirs: &Arc<AtomicUsize>) -> OpenDir {
+        open_dirs.fetch_add(1, Ordering::Relaxed);
+        OpenDir {
+            fd,
+            open_dirs: Arc::clone(open_dirs),
         }
     }
 }
 
-fn regular_scroller(entries: ReadDir, ch: &Sender<ChanResponse>, bar: &ProgressBar, path: PathBuf) {
-    for entry in entries {
-        match entry {
-            Ok(entry) => match entry.metadata() {
-                Ok(metadata) => {
-                    if metadata.is_dir() {
-                        match ch.send(build_dir_chan(entry.path())) {
-                            Ok(_) => {}
-                            Err(e) => {
-                                bar.println(format!(
-                                    "Expect channel to be able to send ERR: 585\n{:?}\n{e:?}",
-                                    entry.path(),
-                                ));
-                            }
-                        };
-                    } else if metadata.is_file() {
-                        match ch.send(build_file_chan(metadata.len())) {
-                            Ok(_) => {}
-                            Err(e) => {
-                                bar.println(format!(
-                                    "Expect channel to be able to send ERR: 9656\n{:?}\n{e:?}",
-                                    entry.path(),
-                                ));
-                            }
-                        };
-                    }
-                }
-                Err(err) => {
-                    bar.println(format!(
-                        "Couldn't get file metadata for {:?}: {}",
-                        entry.path(),
-                        err
-                    ));
-                }
-            },
-            Err(err) => {
-                bar.println(format!(
-                    "Can't display the entry of directory {:?} {err}",
-                    path
-                ));
-            }
+#[cfg(target_os = "linux")]
+impl rustix::fd::AsFd for OpenDir {
+    fn as_fd(&self) -> rustix::fd::BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for OpenDir {
+    fn drop(&mut self) {
+        self.open_dirs.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+pub fn build_dir_chan(path: PathBuf, depth: usize) -> ChanResponse {
+    ChanResponse {
+        t: ResponseType::Dir,
+        path,
+        depth,
+        stats: None,
+        #[cfg(target_os = "linux")]
+        parent: None,
+    }
+}
+/// Signal a directory found in an open parent directory.
+#[cfg(target_os = "linux")]
+pub fn build_dir_chan_at(parent: ParentDir, path: PathBuf, depth: usize) -> ChanResponse {
+    ChanResponse {
+        parent: Some(parent),
+        ..build_dir_chan(path, depth)
+    }
+}
+pub fn build_dir_chan_done(path: PathBuf) -> ChanResponse {
+    ChanResponse {
+        t: ResponseType::DoneDir,
+        path,
+        depth: 0,
+        stats: None,
+        #[cfg(target_os = "linux")]
+        parent: None,
+    }
+}
+/// Signal a queued directory which will not be scanned.
+pub fn build_dir_chan_skipped(path: PathBuf, depth: usize) -> ChanResponse {
+    ChanResponse {
+        t: ResponseType::Skipped,
+        ..build_dir_chan(path, depth)
+    }
+}
+/// Signal the end of the scan of a directory along with the totals of its own files.
+pub fn build_dir_chan_done_with_stats(path: PathBuf, stats: DirStats) -> ChanResponse {
+    ChanResponse {
+        t: ResponseType::DoneDir,
+        path,
+        depth: 0,
+        stats: Some(stats),
+        #[cfg(target_os = "linux")]
+        parent: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn format_time_dates() {
+        assert_eq!(format_time(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        assert_eq!(format_time(at(1_709_210_096)), "2024-02-29T12:34:56Z");
+        // 2000 is a leap year, 2100 is not
+        assert_eq!(format_time(at(951_782_400)), "2000-02-29T00:00:00Z");
+        assert_eq!(format_time(at(4_107_542_400)), "2100-03-01T00:00:00Z");
+        // Times before the epoch are not expected, they are shown as the epoch
+        assert_eq!(
+            format_time(UNIX_EPOCH - Duration::from_secs(1)),
+            "1970-01-01T00:00:00Z"
+        );
+    }
+
+    #[test]
+    fn merge_adds_the_counters() {
+        let limits = [4_000, 1_000_000];
+        let mut res = build_result(Path::new("/scan"), &limits, Unit::Decimal);
+        res.add_file(100, 4_096, &limits);
+        res.depth_mut(1).files += 1;
+
+        let mut other = build_result(Path::new("/scan"), &limits, Unit::Decimal);
+        other.add_file(0, 0, &limits);
+        other.add_file(2_000_000, 2_002_944, &limits);
+        other.depth_mut(3).files += 2;
+        other.add_error(ErrorKind::PermissionDenied);
+        other.directories = 4;
+
+        res.merge(&other);
+        assert_eq!(res.files, 3);
+        assert_eq!(res.bytes, 2_000_100);
+        assert_eq!(res.allocated_bytes, 2_007_040);
+        assert_eq!(res.empty_file, 1);
+        assert_eq!(res.directories, 4);
+        assert_eq!(res.entries(EntryKind::Regular), 3);
+        assert_eq!(res.errors(ErrorKind::PermissionDenied), 1);
+        let files: Vec<usize> = res.buckets.iter().map(|b| b.files).collect();
+        assert_eq!(files, [1, 0, 1]);
+        let depths: Vec<usize> = res.depths.iter().map(|d| d.files).collect();
+        assert_eq!(depths, [0, 1, 0, 2]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn from_io_classes_the_errnos() {
+        use rustix::io::Errno;
+
+        let cases = [
+            (Errno::ACCESS, ErrorKind::PermissionDenied),
+            (Errno::PERM, ErrorKind::PermissionDenied),
+            (Errno::NOENT, ErrorKind::NotFound),
+            (Errno::STALE, ErrorKind::StaleHandle),
+            (Errno::IO, ErrorKind::Io),
+            (Errno::NAMETOOLONG, ErrorKind::InvalidName),
+            (Errno::ILSEQ, ErrorKind::InvalidName),
+            (Errno::NOMEM, ErrorKind::Other),
+        ];
+        for &(errno, kind) in &cases {
+            let err = io::Error::from_raw_os_error(errno.raw_os_error());
+            assert_eq!(ErrorKind::from_io(&err), kind, "{err}");
+        }
+    }
+
+    #[test]
+    fn from_io_classes_the_kinds_without_errno() {
+        let cases = [
+            (io::ErrorKind::PermissionDenied, ErrorKind::PermissionDenied),
+            (io::ErrorKind::NotFound, ErrorKind::NotFound),
+            (io::ErrorKind::InvalidInput, ErrorKind::InvalidName),
+            (io::ErrorKind::Other, ErrorKind::Other),
+        ];
+        for &(io_kind, kind) in &cases {
+            assert_eq!(ErrorKind::from_io(&io::Error::from(io_kind)), kind);
         }
     }
 }
@@ -0,0 +1,274 @@
+use crossbeam_deque::{Injector, Steal, Stealer, Worker};
+
+use std::iter;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError, TryLockError};
+use std::thread::{self, JoinHandle};
+
+/// Fixed number of long lived threads running the jobs pushed into a shared queue.
+/// Every worker owns a state, given to each of its jobs and returned when the pool is closed.
+/// The states of the idle workers can be read while the pool is paused.
+///
+/// Each worker keeps the jobs it takes in batches from the shared queue in its own deque,
+/// and the idle workers steal from the others before going to sleep.
+/// A worker left with jobs in its deque wakes a sleeping one to share them.
+pub(crate) struct Pool<T, S> {
+    shared: Arc<Shared<T>>,
+    states: Vec<Arc<Mutex<S>>>,
+    handles: Vec<JoinHandle<()>>,
+}
+
+struct Shared<T> {
+    injector: Injector<T>,
+    stealers: Vec<Stealer<T>>,
+    /// Number of workers running or looking for a job.
+    busy: AtomicUsize,
+    /// The workers don't take new jobs while paused.
+    paused: AtomicBool,
+    closed: AtomicBool,
+    /// Held to check for jobs before sleeping and to notify, so no wakeup is lost in between.
+    sleep: Mutex<()>,
+    wake: Condvar,
+}
+
+impl<T> Shared<T> {
+    /// True if a job is queued or waiting in the deque of a worker.
+    fn has_jobs(&self) -> bool {
+        !self.injector.is_empty() || self.stealers.iter().any(|s| !s.is_empty())
+    }
+
+    fn notify_one(&self) {
+        let _guard = self.sleep.lock().unwrap_or_else(PoisonError::into_inner);
+        self.wake.notify_one();
+    }
+
+    fn notify_all(&self) {
+        let _guard = self.sleep.lock().unwrap_or_else(PoisonError::into_inner);
+        self.wake.notify_all();
+    }
+}
+
+impl<T: Send + 'static, S: Send + 'static> Pool<T, S> {
+    /// Start `size` workers, each one calling `init` to build its state then `run` on every job.
+    pub(crate) fn new<I, F>(size: usize, init: I, run: F) -> Pool<T, S>
+    where
+        I: Fn() -> S,
+        F: Fn(&mut S, T) + Send + Sync + 'static,
+    {
+        let workers: Vec<Worker<T>> = (0..size.max(1)).map(|_| Worker::new_lifo()).collect();
+        let shared = Arc::new(Shared {
+            injector: Injector::new(),
+            stealers: workers.iter().map(Worker::stealer).collect(),
+            busy: AtomicUsize::new(0),
+            paused: AtomicBool::new(false),
+            closed: AtomicBool::new(false),
+            sleep: Mutex::new(()),
+            wake: Condvar::new(),
+        });
+        let states: Vec<Arc<Mutex<S>>> = workers
+            .iter()
+            .map(|_| Arc::new(Mutex::new(init())))
+            .collect();
+        let run = Arc::new(run);
+
+        let handles = workers
+            .into_iter()
+            .zip(&states)
+            .map(|(local, state)| {
+                let shared = Arc::clone(&shared);
+                let state = Arc::clone(state);
+                let run = Arc::clone(&run);
+                thread::spawn(move || work(&local, &shared, &state, &*run))
+            })
+            .collect();
+
+        Pool {
+            shared,
+            states,
+            handles,
+        }
+    }
+
+    /// Queue a job for the next idle worker.
+    pub(crate) fn push(&self, job: T) {
+        self.shared.injector.push(job);
+        self.shared.notify_one();
+    }
+
+    /// Number of workers running or looking for a job.
+    /// Once the pool is paused, 0 means that no job is running until the pool is resumed.
+    pub(crate) fn busy(&self) -> usize {
+        self.shared.busy.load(Ordering::SeqCst)
+    }
+
+    /// Stop giving jobs to the workers, the running ones are completed.
+    pub(crate) fn pause(&self) {
+        self.shared.paused.store(true, Ordering::SeqCst);
+    }
+
+    pub(crate) fn resume(&self) {
+        self.shared.paused.store(false, Ordering::SeqCst);
+        self.shared.notify_all();
+    }
+
+    /// States of the workers not running a job, only stable while the pool is paused.
+    /// The state of a running job stays locked and is skipped.
+    pub(crate) fn idle_states(&self) -> impl Iterator<Item = MutexGuard<'_, S>> {
+        self.states
+            .iter()
+            .filter_map(|state| match state.try_lock() {
+                Ok(state) => Some(state),
+                Err(TryLockError::Poisoned(e)) => Some(e.into_inner()),
+                Err(TryLockError::WouldBlock) => None,
+            })
+    }
+
+    /// Let the workers finish the queued jobs, wait for them to stop and return their states.
+    pub(crate) fn close(self) -> Vec<S> {
+        self.resume();
+        self.shared.closed.store(true, Ordering::SeqCst);
+        self.shared.notify_all();
+        for handle in self.handles {
+            let _ = handle.join();
+        }
+        self.states
+            .into_iter()
+            .filter_map(|state| Arc::try_unwrap(state).ok())
+            .map(|state| state.into_inner().unwrap_or_else(PoisonError::into_inner))
+            .collect()
+    }
+}
+
+fn work<T, S, F: Fn(&mut S, T)>(local: &Worker<T>, shared: &Shared<T>, state: &Mutex<S>, run: &F) {
+    loop {
+        // Counted as busy before checking the pause, so no job starts once a paused pool is seen idle
+        shared.busy.fetch_add(1, Ordering::SeqCst);
+        let job = if shared.paused.load(Ordering::SeqCst) {
+            None
+        } else {
+            find_job(local, shared)
+        };
+        match job {
+            Some(job) => {
+                if !local.is_empty() {
+                    shared.notify_one();
+                }
+                run(
+                    &mut state.lock().unwrap_or_else(PoisonError::into_inner),
+                    job,
+                );
+                shared.busy.fetch_sub(1, Ordering::SeqCst);
+            }
+            None => {
+                shared.busy.fetch_sub(1, Ordering::SeqCst);
+                // Checked again under the lock: a job pushed, a resume or a close after it notifies the waiting worker
+                let guard = shared.sleep.lock().unwrap_or_else(PoisonError::into_inner);
+                if shared.closed.load(Ordering::SeqCst) {
+                    return;
+                }
+                if shared.paused.load(Ordering::SeqCst) || !shared.has_jobs() {
+                    let _guard = shared
+                        .wake
+                        .wait(guard)
+                        .unwrap_or_else(PoisonError::into_inner);
+                }
+            }
+        }
+    }
+}
+
+/// Take a job from the local deque, else a batch from the shared queue, else a batch from another worker.
+fn find_job<T>(local: &Worker<T>, shared: &Shared<T>) -> Option<T> {
+    local.pop().or_else(|| {
+        iter::repeat_with(|| {
+            shared.injector.steal_batch_and_pop(local).or_else(|| {
+                shared
+                    .stealers
+                    .iter()
+                    .map(|s| s.steal_batch_and_pop(local))
+                    .collect()
+            })
+        })
+        .find(|s| !s.is_retry())
+        .and_then(Steal::success)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    /// Wait until the condition holds, failing after a few seconds.
+    fn wait_for(condition: impl Fn() -> bool) {
+        let start = Instant::now();
+        while !condition() {
+            assert!(start.elapsed() < Duration::from_secs(10), "timed out");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn every_job_runs_once() {
+        let pool = Pool::new(4, Vec::new, |done: &mut Vec<usize>, job| done.push(job));
+        for job in 0..10_000 {
+            pool.push(job);
+        }
+        let mut done: Vec<usize> = pool.close().into_iter().flatten().collect();
+        done.sort_unstable();
+        assert_eq!(done, (0..10_000).collect::<Vec<usize>>());
+    }
+
+    #[test]
+    fn no_job_runs_while_paused() {
+        let ran = Arc::new(AtomicUsize::new(0));
+        let pool = {
+            let ran = Arc::clone(&ran);
+            Pool::new(
+                4,
+                || 0,
+                move |count: &mut usize, _job: usize| {
+                    thread::sleep(Duration::from_millis(1));
+                    *count += 1;
+                    ran.fetch_add(1, Ordering::SeqCst);
+                },
+            )
+        };
+        for job in 0..500 {
+            pool.push(job);
+        }
+        wait_for(|| ran.load(Ordering::SeqCst) > 0);
+        pool.pause();
+        // The running jobs end, then no other one starts
+        wait_for(|| pool.busy() == 0);
+        let paused_at = ran.load(Ordering::SeqCst);
+        assert!(paused_at < 500);
+        assert_eq!(pool.idle_states().count(), 4);
+        assert_eq!(
+            pool.idle_states().map(|count| *count).sum::<usize>(),
+            paused_at
+        );
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(pool.busy(), 0);
+        assert_eq!(ran.load(Ordering::SeqCst), paused_at);
+
+        pool.resume();
+        assert_eq!(pool.close().iter().sum::<usize>(), 500);
+    }
+
+    #[test]
+    fn close_returns_every_state() {
+        let next = AtomicUsize::new(0);
+        let pool = Pool::new(
+            8,
+            || next.fetch_add(1, Ordering::SeqCst),
+            |_: &mut usize, _job: ()| {},
+        );
+        // A paused pool is resumed to be closed
+        pool.push(());
+        pool.pause();
+        let mut states = pool.close();
+        states.sort_unstable();
+        assert_eq!(states, (0..8).collect::<Vec<usize>>());
+    }
+}
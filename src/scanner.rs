@@ -0,0 +1,1955 @@
+use colored::Colorize;
+use indicatif::{ProgressBar, ProgressStyle};
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::time;
+
+use crate::ages::{build_age_buckets, TimeField, DEFAULT_AGE_LIMITS};
+use crate::buckets::Unit;
+use crate::checkpoint::{Checkpoint, RawPath};
+use crate::filter::Filter;
+use crate::objects::{
+    self, build_dir_chan, build_dir_chan_done, build_dir_chan_done_with_stats,
+    build_dir_chan_skipped, ChanResponse, DirStats, EntryKind, ErrorKind, ExtensionStats,
+    MatrixCell, OwnerStats, LONG_EXTENSIONS, MAX_EXTENSION_LEN,
+};
+#[cfg(target_os = "linux")]
+use crate::objects::{build_dir_chan_at, OpenDir, ParentDir};
+use crate::pool::Pool;
+
+/// Maximum number of directories kept open for their subdirectories, below the usual limit of open files.
+/// Past it the subdirectories are opened from their full path, unless it's too long to be opened at once.
+#[cfg(target_os = "linux")]
+const MAX_OPEN_DIRS: usize = 512;
+
+/// Maximum length of a path given to the system, with its final NUL byte.
+#[cfg(target_os = "linux")]
+const PATH_MAX: usize = 4096;
+
+/// Time between two checkpoints of the scan state, when requested.
+const DEFAULT_CHECKPOINT_INTERVAL: time::Duration = time::Duration::from_secs(5 * 60);
+
+/// Size of the buffer reading the entries of a directory with statx.
+#[cfg(target_os = "linux")]
+const DIRENT_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Configure and run a recursive scan from a root directory.
+///
+/// Every setting has a default, so the minimal usage is `Scanner::new(path).scan()`.
+#[derive(Debug, Clone)]
+pub struct Scanner {
+    path: PathBuf,
+    max_threads: usize,
+    statx: bool,
+    verbose: bool,
+    progress: bool,
+    unit: Unit,
+    limits: Option<Vec<u64>>,
+    top_dirs: usize,
+    top_dirs_depth: usize,
+    largest_files: usize,
+    allocated: bool,
+    filter: Filter,
+    one_file_system: bool,
+    max_depth: Option<usize>,
+    age: Option<TimeField>,
+    matrix: bool,
+    owners: bool,
+    top_extensions: usize,
+    fold_case: bool,
+    error_log: Option<PathBuf>,
+    stop: Arc<AtomicBool>,
+    checkpoint: Option<PathBuf>,
+    checkpoint_interval: time::Duration,
+    resume: Option<Checkpoint>,
+}
+
+/// Scan continued from a checkpoint, built by [`Scanner::resume`] once the settings are checked.
+#[derive(Debug, Clone)]
+pub struct Resumed(Scanner);
+
+impl Resumed {
+    /// Scan the directories left and return the result merged with the one of the checkpoint.
+    pub fn scan(&self) -> objects::Result {
+        self.0.scan()
+    }
+}
+
+/// Settings shared with the threads scanning the directories.
+struct Walker {
+    statx_capable: bool,
+    limits: Vec<u64>,
+    /// Collect the totals of every directory to build the top directories report.
+    dir_stats: bool,
+    largest_files: usize,
+    allocated: bool,
+    filter: Filter,
+    /// Timestamp sent with the files for the age ranges.
+    age: Option<TimeField>,
+    /// True if the user and group ids are sent with the files.
+    owners: bool,
+    /// True if the extensions are sent with the files.
+    extensions: bool,
+    /// True if the extensions are lowercased.
+    fold_case: bool,
+    /// Device of the scanned path if the scan must stay on its filesystem.
+    root_dev: Option<u64>,
+    /// Number of directories open with statx, by the workers and for the queued subdirectories.
+    #[cfg(target_os = "linux")]
+    open_dirs: Arc<AtomicUsize>,
+    /// Device and inode of the files with several names already counted.
+    linked_inodes: Mutex<HashSet<(u64, u64)>>,
+    /// Smallest size of the largest files kept so far once the list is full.
+    /// Only bigger files are kept with their path.
+    largest_threshold: AtomicU64,
+    /// File receiving the failing paths with their error, if requested.
+    error_log: Option<Mutex<BufWriter<File>>>,
+    /// Set to stop the scan, the queued directories are then skipped.
+    stop: Arc<AtomicBool>,
+    /// Files accounted by the workers so far, for the progress bar.
+    scanned_files: AtomicUsize,
+    /// True if the state of the scan is saved periodically, the workers then keep the subdirectories
+    /// queued for the directory they scan.
+    checkpoints: bool,
+    /// Set while a checkpoint waits for the workers to stop between two entries.
+    holding: AtomicBool,
+    /// Workers stopped for the checkpoint.
+    held: Mutex<Held>,
+    /// Wakes the held workers once the checkpoint is saved.
+    released: Condvar,
+}
+
+impl Scanner {
+    /// Build a scanner starting at the given directory.
+    pub fn new<P: Into<PathBuf>>(path: P) -> Scanner {
+        Scanner {
+            path: path.into(),
+            max_threads: 0,
+            statx: true,
+            verbose: false,
+            progress: false,
+            unit: Unit::Decimal,
+            limits: None,
+            top_dirs: 0,
+            top_dirs_depth: 3,
+            largest_files: 0,
+            allocated: false,
+            filter: Filter::default(),
+            one_file_system: false,
+            max_depth: None,
+            age: None,
+            matrix: false,
+            owners: false,
+            top_extensions: 0,
+            fold_case: false,
+            error_log: None,
+            stop: Arc::new(AtomicBool::new(false)),
+            checkpoint: None,
+            checkpoint_interval: DEFAULT_CHECKPOINT_INTERVAL,
+            resume: None,
+        }
+    }
+
+    /// Number of worker threads scanning the directories. If 0, 4 times the number of detected logical CPU.
+    pub fn max_threads(mut self, max_threads: usize) -> Scanner {
+        self.max_threads = max_threads;
+        self
+    }
+
+    /// Use statx on linux when the system supports it. No effect on other targets.
+    pub fn statx(mut self, statx: bool) -> Scanner {
+        self.statx = statx;
+        self
+    }
+
+    /// Print additional information while scanning.
+    pub fn verbose(mut self, verbose: bool) -> Scanner {
+        self.verbose = verbose;
+        self
+    }
+
+    /// Display a progress bar on the terminal while scanning.
+    pub fn progress(mut self, progress: bool) -> Scanner {
+        self.progress = progress;
+        self
+    }
+
+    /// Upper limits in bytes of the size ranges of the report, see [`crate::buckets::parse_limits`].
+    /// The limits are sorted and the duplicates or null ones are ignored.
+    pub fn buckets(mut self, mut limits: Vec<u64>) -> Scanner {
+        limits.sort_unstable();
+        limits.dedup();
+        limits.retain(|l| *l != 0);
+        self.limits = Some(limits);
+        self
+    }
+
+    /// Units of the size ranges. If no limits are given, the default ones of the unit are used.
+    pub fn unit(mut self, unit: Unit) -> Scanner {
+        self.unit = unit;
+        self
+    }
+
+    /// Number of directories to keep in the top directories reports, by files and by bytes.
+    /// If 0 the totals per directory are not collected.
+    pub fn top_dirs(mut self, top_dirs: usize) -> Scanner {
+        self.top_dirs = top_dirs;
+        self
+    }
+
+    /// Maximum depth, relative to the scanned path, of the directories listed in the top directories reports.
+    /// The totals of the deeper directories are included in their parents.
+    pub fn top_dirs_depth(mut self, depth: usize) -> Scanner {
+        self.top_dirs_depth = depth;
+        self
+    }
+
+    /// Number of largest files to list with their path. If 0 the list is disabled.
+    pub fn largest_files(mut self, largest_files: usize) -> Scanner {
+        self.largest_files = largest_files;
+        self
+    }
+
+    /// Collect the allocated size on disk from the number of blocks, next to the apparent size.
+    /// Not available on Windows.
+    pub fn allocated(mut self, allocated: bool) -> Scanner {
+        self.allocated = allocated;
+        self
+    }
+
+    /// Patterns of the entries to skip. Excluded directories are not opened.
+    pub fn filter(mut self, filter: Filter) -> Scanner {
+        self.filter = filter;
+        self
+    }
+
+    /// Skip the directories on another filesystem than the scanned path, like mount points. No effect on Windows.
+    pub fn one_file_system(mut self, one_file_system: bool) -> Scanner {
+        self.one_file_system = one_file_system;
+        self
+    }
+
+    /// Maximum depth of the scanned entries, the entries of the given path being at depth 1.
+    /// The directories at the maximum depth are counted but not scanned.
+    pub fn max_depth(mut self, max_depth: Option<usize>) -> Scanner {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Build the age ranges of the files from the given timestamp.
+    pub fn age(mut self, age: Option<TimeField>) -> Scanner {
+        self.age = age;
+        self
+    }
+
+    /// Cross the size ranges with the age ranges. Only used when an age timestamp is given.
+    pub fn matrix(mut self, matrix: bool) -> Scanner {
+        self.matrix = matrix;
+        self
+    }
+
+    /// Collect the totals per user and group owning the files. No effect on Windows.
+    pub fn owners(mut self, owners: bool) -> Scanner {
+        self.owners = owners;
+        self
+    }
+
+    /// Number of extensions to keep in the top extensions reports, by files and by bytes.
+    /// If 0 the totals per extension are not collected.
+    pub fn top_extensions(mut self, top_extensions: usize) -> Scanner {
+        self.top_extensions = top_extensions;
+        self
+    }
+
+    /// Group the extensions regardless of their case, like "JPG" and "jpg".
+    pub fn fold_case(mut self, fold_case: bool) -> Scanner {
+        self.fold_case = fold_case;
+        self
+    }
+
+    /// Write every failing path with its error number and class to the given file, one per line.
+    /// The errors are counted in the result either way.
+    pub fn error_log(mut self, error_log: Option<PathBuf>) -> Scanner {
+        self.error_log = error_log;
+        self
+    }
+
+    /// Flag stopping the scan once set, for instance from a signal handler.
+    /// The directories being scanned are completed, the queued ones are skipped and the partial result is returned.
+    pub fn stop_flag(mut self, stop: Arc<AtomicBool>) -> Scanner {
+        self.stop = stop;
+        self
+    }
+
+    /// Save the state of the scan to the given file periodically and when the scan is stopped,
+    /// to continue it later with [`Scanner::resume`]. The file is removed once the scan is complete.
+    pub fn checkpoint(mut self, file: Option<PathBuf>) -> Scanner {
+        self.checkpoint = file;
+        self
+    }
+
+    /// Time between two checkpoints, 5 minutes by default.
+    /// The workers stop between two entries while a checkpoint is written, the directories being scanned
+    /// are saved as left to scan.
+    pub fn checkpoint_interval(mut self, interval: time::Duration) -> Scanner {
+        self.checkpoint_interval = interval;
+        self
+    }
+
+    /// Continue an unfinished scan from its checkpoint instead of starting from the path.
+    /// The settings must be the ones of the unfinished scan, they can't be changed afterwards.
+    pub fn resume(mut self, checkpoint: Checkpoint) -> Result<Resumed, String> {
+        if checkpoint.settings != self.settings() {
+            return Err(
+                "the settings changing the totals differ from the ones of the checkpoint"
+                    .to_string(),
+            );
+        }
+        self.path = checkpoint.path().to_path_buf();
+        #[cfg(target_os = "linux")]
+        if statx_supported(&self.path, self.statx, false) != checkpoint.result.statx {
+            return Err("statx is not available as it was for the checkpoint".to_string());
+        }
+        self.resume = Some(checkpoint);
+        Ok(Resumed(self))
+    }
+
+    /// Run the scan and return the collected result.
+    pub fn scan(&self) -> objects::Result {
+        let max_threads = if self.max_threads == 0 {
+            num_cpus::get() * 4
+        } else {
+            self.max_threads
+        };
+
+        #[cfg(target_os = "linux")]
+        let statx_capable = statx_supported(&self.path, self.statx, self.verbose);
+        #[cfg(not(target_os = "linux"))]
+        let statx_capable = false;
+
+        let limits = self.limits();
+        let mut res = objects::build_result(&self.path, &limits, self.unit);
+        res.statx = statx_capable;
+        res.allocated = self.allocated && cfg!(unix);
+        res.owners = self.owners && cfg!(unix);
+        if self.age.is_some() {
+            res.age_field = self.age;
+            res.ages = build_age_buckets(&DEFAULT_AGE_LIMITS);
+            if self.matrix {
+                res.matrix = vec![vec![MatrixCell::default(); res.ages.len()]; res.buckets.len()];
+            }
+        }
+
+        let starting_point = time::Instant::now();
+        res.start_time = time::SystemTime::now();
+
+        // The workers start from an empty result, the totals restored from a checkpoint are kept aside
+        let mut ranked = Ranked::default();
+        let mut dir_totals: HashMap<PathBuf, DirStats> = HashMap::new();
+        let mut linked_inodes = HashSet::new();
+        let mut elapsed = time::Duration::new(0, 0);
+        let empty = match &self.resume {
+            Some(checkpoint) => {
+                // The ages stay computed from the start of the unfinished scan
+                res.start_time = checkpoint.result.start_time;
+                let empty = res.clone();
+                res = checkpoint.result.clone();
+                res.statx = statx_capable;
+                res.interrupted = false;
+                res.queued_dirs = 0;
+                elapsed = checkpoint.elapsed;
+                ranked = Ranked::restore(checkpoint);
+                dir_totals = checkpoint
+                    .dir_totals
+                    .iter()
+                    .map(|(path, stats)| (path.0.clone(), stats.clone()))
+                    .collect();
+                linked_inodes = checkpoint.linked_inodes.iter().copied().collect();
+                empty
+            }
+            None => res.clone(),
+        };
+
+        let walker = Arc::new(Walker {
+            statx_capable,
+            limits,
+            dir_stats: self.top_dirs > 0,
+            largest_files: self.largest_files,
+            allocated: res.allocated,
+            filter: self.filter.clone(),
+            age: self.age,
+            owners: res.owners,
+            extensions: self.top_extensions > 0,
+            fold_case: self.fold_case,
+            root_dev: self.root_dev(),
+            error_log: self.open_error_log(),
+            stop: Arc::clone(&self.stop),
+            #[cfg(target_os = "linux")]
+            open_dirs: Arc::new(AtomicUsize::new(0)),
+            linked_inodes: Mutex::new(linked_inodes),
+            largest_threshold: AtomicU64::new(0),
+            scanned_files: AtomicUsize::new(res.files),
+            checkpoints: self.checkpoint.is_some(),
+            holding: AtomicBool::new(false),
+            held: Mutex::new(Held::default()),
+            released: Condvar::new(),
+        });
+
+        // build channel
+        let (sender, receiver) = channel();
+
+        let bar = if self.progress {
+            let bar = ProgressBar::new(max_threads as u64);
+            bar.set_style(
+                ProgressStyle::default_bar()
+                    .template("{elapsed} {bar:.cyan/blue} {pos:>3}/{len:3} {msg}")
+                    .unwrap()
+                    .progress_chars("##-"),
+            );
+            bar
+        } else {
+            ProgressBar::hidden()
+        };
+
+        // Long lived workers opening and scrolling the directories, each one accounting its files in its own tally.
+        // The loop below only dispatches the directories and aggregates their totals.
+        let pool = {
+            let walker = Arc::clone(&walker);
+            let sender = sender.clone();
+            let bar = bar.clone();
+            let largest_files = self.largest_files;
+            Pool::new(
+                max_threads,
+                move || Tally::new(empty.clone(), largest_files),
+                move |tally, job| walker.handle_dir(job, tally, &sender, &bar),
+            )
+        };
+
+        let mut dispatch = Dispatch {
+            pool: &pool,
+            res,
+            max_depth: self.max_depth,
+            top_dirs_depth: self.top_dirs_depth,
+            root_depth: self.path.components().count(),
+            dir_totals,
+            pending: HashMap::new(),
+            unscanned: Vec::new(),
+        };
+
+        // Start scanning at the given path, or from the directories left by the unfinished scan
+        match &self.resume {
+            Some(checkpoint) => {
+                let mut sent: HashMap<PathBuf, HashSet<OsString>> = checkpoint
+                    .sent
+                    .iter()
+                    .map(|(path, names)| {
+                        let names = names.iter().map(|n| n.0.clone().into_os_string());
+                        (path.0.clone(), names.collect())
+                    })
+                    .collect();
+                for (path, depth) in &checkpoint.pending {
+                    dispatch.push(DirJob {
+                        sent: sent.remove(&path.0).unwrap_or_default(),
+                        ..DirJob::new(path.0.clone(), *depth)
+                    });
+                }
+            }
+            None => dispatch.push(DirJob::new(self.path.clone(), 0)),
+        }
+
+        let display_refresh_time = time::Duration::from_millis(250);
+        let mut last_message = time::Instant::now()
+            .checked_sub(display_refresh_time)
+            .expect("to remove some time");
+        let mut last_checkpoint = time::Instant::now();
+        let mut holding = false;
+
+        // Handle responses until every directory is done
+        while !dispatch.pending.is_empty() {
+            match receiver.recv_timeout(display_refresh_time) {
+                Ok(received) => dispatch.handle(received, &walker),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+
+            //  Limit the display refresh
+            let dur = time::Instant::now().duration_since(last_message);
+            if dur > display_refresh_time {
+                let running = pool.busy();
+                bar.set_message(format!(
+                    "files scanned {} and dirs in queue {}",
+                    walker.scanned_files.load(Ordering::Relaxed),
+                    dispatch.pending.len().saturating_sub(running)
+                ));
+                bar.set_position(running as u64);
+
+                last_message = time::Instant::now();
+            }
+
+            if walker.stopped() && !dispatch.res.interrupted {
+                dispatch.res.interrupted = true;
+                bar.println("Interrupted, waiting for the directories being scanned");
+                // The workers must run to drop the queued directories
+                if holding {
+                    walker.release_workers();
+                    pool.resume();
+                    holding = false;
+                }
+            }
+
+            let file = match &self.checkpoint {
+                Some(file) if !walker.stopped() => file,
+                _ => continue,
+            };
+            if !holding && last_checkpoint.elapsed() >= self.checkpoint_interval {
+                walker.hold_workers();
+                pool.pause();
+                holding = true;
+            }
+            if !holding {
+                continue;
+            }
+            // Once every busy worker is held, the totals of the workers match the directories done
+            // and the directories being scanned are left to scan
+            let held = {
+                let mut held = walker.held.lock().unwrap_or_else(PoisonError::into_inner);
+                if pool.busy() != held.workers.len() {
+                    continue;
+                }
+                mem::take(&mut held.workers)
+            };
+            // The workers sent the subdirectories queued so far, and the idle ones their last messages
+            while let Ok(received) = receiver.try_recv() {
+                dispatch.handle(received, &walker);
+            }
+
+            let mut totals = Totals {
+                res: dispatch.res.clone(),
+                ranked: ranked.clone(),
+            };
+            for tally in pool.idle_states() {
+                totals.merge(&tally.done, self.largest_files);
+            }
+            let mut linked_inodes = walker
+                .linked_inodes
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clone();
+            let mut sent = Vec::new();
+            for worker in held {
+                totals.merge(&worker.done, self.largest_files);
+                // The files first seen in the directories left are counted when they are scanned again
+                for inode in &worker.linked {
+                    linked_inodes.remove(inode);
+                }
+                sent.push((worker.dir, worker.sent));
+            }
+            let pending = dispatch
+                .pending
+                .iter()
+                .map(|(path, depth)| (path.clone(), *depth))
+                .collect();
+            let checkpoint = self.snapshot(
+                totals,
+                &dispatch.dir_totals,
+                linked_inodes,
+                pending,
+                sent,
+                elapsed + starting_point.elapsed(),
+            );
+            save_checkpoint(&checkpoint, file, &bar);
+            // The errors of the saved totals are kept if the scan is killed
+            if let Err(e) = walker.flush_error_log() {
+                bar.println(format!(
+                    "can't write the error log: {:}",
+                    e.to_string().red()
+                ));
+            }
+
+            walker.release_workers();
+            pool.resume();
+            holding = false;
+            last_checkpoint = time::Instant::now();
+        }
+        // A checkpoint may be requested while the last directories end
+        if holding {
+            walker.release_workers();
+        }
+        bar.set_message(format!(
+            "Total file scanned {}",
+            walker.scanned_files.load(Ordering::Relaxed)
+        ));
+        bar.finish();
+
+        let Dispatch {
+            res,
+            dir_totals,
+            unscanned,
+            ..
+        } = dispatch;
+
+        // Gather the tallies of the workers
+        let mut totals = Totals { res, ranked };
+        for mut tally in pool.close() {
+            tally.merge_current();
+            totals.merge(&tally.done, self.largest_files);
+        }
+        let Totals { mut res, ranked } = totals;
+        res.skipped_mounts.sort();
+        res.queued_dirs = unscanned.len();
+
+        // Save the time spend
+        elapsed += starting_point.elapsed();
+        res.duration = elapsed;
+
+        // A stopped scan can be continued from the directories left, a complete one doesn't need its checkpoint
+        if let Some(file) = &self.checkpoint {
+            if res.interrupted {
+                let totals = Totals {
+                    res: res.clone(),
+                    ranked: ranked.clone(),
+                };
+                let linked_inodes = walker
+                    .linked_inodes
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .clone();
+                let checkpoint = self.snapshot(
+                    totals,
+                    &dir_totals,
+                    linked_inodes,
+                    unscanned,
+                    Vec::new(),
+                    elapsed,
+                );
+                save_checkpoint(&checkpoint, file, &bar);
+            } else if let Err(e) = fs::remove_file(file) {
+                if e.kind() != io::ErrorKind::NotFound {
+                    eprintln!(
+                        "can't remove the checkpoint {:?}: {:}",
+                        file,
+                        e.to_string().red()
+                    );
+                }
+            }
+        }
+
+        if self.top_dirs > 0 {
+            let mut dirs: Vec<DirStats> = dir_totals
+                .into_iter()
+                .map(|(path, mut stats)| {
+                    stats.path = path.to_string_lossy().to_string();
+                    stats
+                })
+                .collect();
+
+            // The paths order the directories with the same totals, the report doesn't change between runs
+            dirs.sort_by(|a, b| b.files.cmp(&a.files).then_with(|| a.path.cmp(&b.path)));
+            res.top_dirs_by_files = dirs.iter().take(self.top_dirs).cloned().collect();
+            dirs.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.path.cmp(&b.path)));
+            dirs.truncate(self.top_dirs);
+            res.top_dirs_by_bytes = dirs;
+        }
+
+        if self.top_extensions > 0 {
+            let mut list: Vec<ExtensionStats> = ranked.extensions.into_values().collect();
+            // Same totals ordered by extension, like the directories
+            list.sort_by(|a, b| {
+                b.files
+                    .cmp(&a.files)
+                    .then_with(|| a.extension.cmp(&b.extension))
+            });
+            res.top_extensions_by_files = list.iter().take(self.top_extensions).cloned().collect();
+            list.sort_by(|a, b| {
+                b.bytes
+                    .cmp(&a.bytes)
+                    .then_with(|| a.extension.cmp(&b.extension))
+            });
+            list.truncate(self.top_extensions);
+            res.top_extensions_by_bytes = list;
+        }
+
+        if res.owners {
+            res.users = owner_list(ranked.users, user_name);
+            res.groups = owner_list(ranked.groups, group_name);
+        }
+
+        if let Err(e) = walker.flush_error_log() {
+            eprintln!("can't write the error log: {:}", e.to_string().red());
+        }
+
+        res.largest_files = ranked
+            .largest
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse((size, Reverse(path)))| objects::FileSize {
+                path: path.to_string_lossy().to_string(),
+                size,
+            })
+            .collect();
+
+        res
+    }
+}
+
+/// Write a checkpoint, a failure is displayed and doesn't stop the scan.
+fn save_checkpoint(checkpoint: &Checkpoint, file: &Path, bar: &ProgressBar) {
+    if let Err(e) = checkpoint.save(file) {
+        bar.println(format!(
+            "can't save the checkpoint {:?}: {:}",
+            file,
+            e.to_string().red()
+        ));
+    }
+}
+
+impl Scanner {
+    /// Upper limits of the size ranges.
+    fn limits(&self) -> Vec<u64> {
+        match &self.limits {
+            Some(limits) => limits.clone(),
+            None => self.unit.default_limits(),
+        }
+    }
+
+    /// Settings changing the collected totals, a scan is only resumed with the same ones.
+    /// The linked files restored from the checkpoint must be read the same way, with or without statx.
+    fn settings(&self) -> String {
+        format!(
+            "{:?}",
+            (
+                (
+                    self.limits(),
+                    self.unit,
+                    self.allocated,
+                    self.owners,
+                    self.statx
+                ),
+                (
+                    self.age,
+                    self.matrix,
+                    self.top_extensions > 0,
+                    self.fold_case
+                ),
+                (self.top_dirs > 0, self.top_dirs_depth, self.largest_files),
+                (&self.filter, self.one_file_system, self.max_depth),
+            )
+        )
+    }
+
+    /// State of the scan from the merged totals and the directories left.
+    /// The subdirectories already queued by the directories left are given with them.
+    fn snapshot(
+        &self,
+        totals: Totals,
+        dir_totals: &HashMap<PathBuf, DirStats>,
+        linked_inodes: HashSet<(u64, u64)>,
+        pending: Vec<(PathBuf, usize)>,
+        sent: Vec<(PathBuf, Vec<OsString>)>,
+        elapsed: time::Duration,
+    ) -> Checkpoint {
+        let Totals { res, ranked } = totals;
+        let mut checkpoint = Checkpoint::new(self.settings(), self.path.clone(), res);
+        checkpoint.elapsed = elapsed;
+        checkpoint.dir_totals = dir_totals
+            .iter()
+            .map(|(path, stats)| (RawPath(path.clone()), stats.clone()))
+            .collect();
+        checkpoint.users = ranked.users.into_iter().collect();
+        checkpoint.groups = ranked.groups.into_iter().collect();
+        checkpoint.extensions = ranked.extensions.into_iter().collect();
+        checkpoint.largest = ranked
+            .largest
+            .into_iter()
+            .map(|Reverse((size, Reverse(path)))| (size, RawPath(path)))
+            .collect();
+        checkpoint.linked_inodes = linked_inodes.into_iter().collect();
+        checkpoint.pending = pending
+            .into_iter()
+            .map(|(path, depth)| (RawPath(path), depth))
+            .collect();
+        checkpoint.sent = sent
+            .into_iter()
+            .map(|(path, names)| {
+                let names = names.into_iter().map(|n| RawPath(PathBuf::from(n)));
+                (RawPath(path), names.collect())
+            })
+            .collect();
+        checkpoint
+    }
+
+    /// Create the error log file, if requested.
+    /// A resumed scan appends to it, the errors counted before the checkpoint are already listed.
+    fn open_error_log(&self) -> Option<Mutex<BufWriter<File>>> {
+        let path = self.error_log.as_ref()?;
+        let opened = if self.resume.is_some() {
+            OpenOptions::new().create(true).append(true).open(path)
+        } else {
+            File::create(path)
+        };
+        match opened {
+            Ok(file) => Some(Mutex::new(BufWriter::new(file))),
+            Err(e) => {
+                eprintln!(
+                    "can't create the error log {:?}, the errors are only counted: {:}",
+                    path,
+                    e.to_string().red()
+                );
+                None
+            }
+        }
+    }
+
+    /// Device of the scanned path when the scan must stay on its filesystem.
+    fn root_dev(&self) -> Option<u64> {
+        if !self.one_file_system {
+            return None;
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            match fs::metadata(&self.path) {
+                Ok(metadata) => return Some(metadata.dev()),
+                Err(e) => eprintln!(
+                    "can't get the device of {:?}, the scan may cross filesystems: {:}",
+                    self.path,
+                    e.to_string().red()
+                ),
+            }
+        }
+        None
+    }
+}
+
+impl Walker {
+    /// True if the directory is on another filesystem than the scanned path and must be skipped.
+    /// The device of the directory is only read when the scan must stay on its filesystem.
+    fn other_fs<F>(&self, tally: &mut Tally, dir: &Path, name: &OsStr, dev: F) -> bool
+    where
+        F: FnOnce() -> Option<u64>,
+    {
+        let root_dev = match self.root_dev {
+            Some(root_dev) => root_dev,
+            None => return false,
+        };
+        if dev().is_some_and(|dev| dev != root_dev) {
+            tally
+                .current
+                .res
+                .skipped_mounts
+                .push(dir.join(name).to_string_lossy().to_string());
+            return true;
+        }
+        false
+    }
+
+    /// True if the entry is skipped because of the filter.
+    fn pruned(&self, tally: &mut Tally, dir: &Path, name: &OsStr, is_dir: bool) -> bool {
+        if self.filter.is_empty() {
+            return false;
+        }
+        let pruned = self.filter.prunes(&dir.join(name), is_dir);
+        if pruned {
+            tally.current.res.pruned += 1;
+        }
+        pruned
+    }
+
+    /// Count an error in the tally of the worker, write it in the error log if any and display it.
+    fn error(
+        &self,
+        tally: &mut Tally,
+        bar: &ProgressBar,
+        message: &str,
+        path: &Path,
+        err: &io::Error,
+    ) {
+        let kind = ErrorKind::from_io(err);
+        tally.current.res.add_error(kind);
+        if let Some(log) = &self.error_log {
+            let mut log = log.lock().unwrap_or_else(PoisonError::into_inner);
+            // A failing log doesn't stop the scan, the errors are still counted
+            let _ = write_error(&mut *log, path, kind, err);
+        }
+        bar.println(format!(
+            "{message} {}: {}",
+            path.to_string_lossy().blue(),
+            err.to_string().bold()
+        ));
+    }
+
+    /// Write the buffered lines of the error log, if any.
+    fn flush_error_log(&self) -> io::Result<()> {
+        match &self.error_log {
+            Some(log) => log.lock().unwrap_or_else(PoisonError::into_inner).flush(),
+            None => Ok(()),
+        }
+    }
+
+    fn stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+
+    /// Ask the workers to stop at their next entry while a checkpoint is saved.
+    fn hold_workers(&self) {
+        let mut held = self.held.lock().unwrap_or_else(PoisonError::into_inner);
+        held.round += 1;
+        self.holding.store(true, Ordering::Relaxed);
+    }
+
+    /// Let the held workers continue once the checkpoint is saved or given up.
+    fn release_workers(&self) {
+        let mut held = self.held.lock().unwrap_or_else(PoisonError::into_inner);
+        held.released = held.round;
+        held.workers.clear();
+        self.holding.store(false, Ordering::Relaxed);
+        self.released.notify_all();
+    }
+
+    /// Wait between two entries while a checkpoint is saved, called by the workers.
+    /// The worker hands over the totals of its directories done, the one being scanned is left to scan.
+    fn hold(&self, tally: &Tally, dir: &Path) {
+        if !self.holding.load(Ordering::Relaxed) {
+            return;
+        }
+        let mut held = self.held.lock().unwrap_or_else(PoisonError::into_inner);
+        // Checked again under the lock, the checkpoint may be done already
+        if !self.holding.load(Ordering::Relaxed) {
+            return;
+        }
+        let round = held.round;
+        held.workers.push(HeldWorker {
+            done: tally.done.clone(),
+            dir: dir.to_path_buf(),
+            sent: tally.sent.clone(),
+            linked: tally.linked.clone(),
+        });
+        while held.released < round {
+            held = self
+                .released
+                .wait(held)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    /// True if the file must be counted: it has a single name or it's the first name seen for it.
+    fn first_link(&self, tally: &mut Tally, nlink: u64, dev: u64, ino: u64) -> bool {
+        if nlink <= 1 {
+            return true;
+        }
+        let first = self
+            .linked_inodes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert((dev, ino));
+        if !first {
+            tally.current.res.extra_links += 1;
+        } else if self.checkpoints {
+            tally.linked.push((dev, ino));
+        }
+        first
+    }
+
+    /// Queue a subdirectory of the directory being scanned, it's kept for the checkpoints.
+    fn send_dir(
+        &self,
+        tally: &mut Tally,
+        ch: &Sender<ChanResponse>,
+        bar: &ProgressBar,
+        name: &OsStr,
+        queued: ChanResponse,
+    ) {
+        match ch.send(queued) {
+            Ok(_) if self.checkpoints => tally.sent.push(name.to_os_string()),
+            Ok(_) => {}
+            Err(e) => bar.println(format!("Can't queue the directory {:?}: {e}", e.0.path)),
+        }
+    }
+
+    /// Raise the threshold of the largest files from a full list of a worker,
+    /// its smallest file is a lower bound for all of them.
+    fn raise_largest_threshold(&self, largest: &Largest) {
+        if largest.len() == self.largest_files {
+            if let Some(Reverse((len, _))) = largest.peek() {
+                self.largest_threshold.fetch_max(*len, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Path of the entry if the file may be one of the largest ones, an empty path otherwise.
+    /// The files of the size of the threshold are kept, the ones with the same size are chosen by path.
+    fn largest_candidate(&self, len: u64, dir: &Path, name: &OsStr) -> PathBuf {
+        let threshold = self.largest_threshold.load(Ordering::Relaxed).max(1);
+        if self.largest_files > 0 && len >= threshold {
+            dir.join(name)
+        } else {
+            PathBuf::new()
+        }
+    }
+
+    /// Extension of a file name if requested, empty if it has none.
+    fn extension(&self, name: &OsStr) -> Option<String> {
+        if !self.extensions {
+            return None;
+        }
+        let extension = match Path::new(name).extension() {
+            Some(e) => e.to_string_lossy(),
+            None => return Some(String::new()),
+        };
+        if extension.chars().count() > MAX_EXTENSION_LEN {
+            return Some(LONG_EXTENSIONS.to_string());
+        }
+        if self.fold_case {
+            Some(extension.to_lowercase())
+        } else {
+            Some(extension.to_string())
+        }
+    }
+
+    /// Open a directory and scroll its entries, run by the workers.
+    fn handle_dir(
+        &self,
+        job: DirJob,
+        tally: &mut Tally,
+        ch: &Sender<ChanResponse>,
+        bar: &ProgressBar,
+    ) {
+        // The queued directories are dropped once the scan is stopped
+        if self.stopped() {
+            if let Err(e) = ch.send(build_dir_chan_skipped(job.path, job.depth)) {
+                bar.println(format!("Can't signal a skipped directory: {e}"));
+            }
+            return;
+        }
+
+        let DirJob {
+            path,
+            depth,
+            sent,
+            #[cfg(target_os = "linux")]
+            parent,
+        } = job;
+        if self.checkpoints {
+            tally.sent.extend(sent.iter().cloned());
+        }
+
+        let files = tally.current.res.files;
+        #[cfg(target_os = "linux")]
+        let scrolled = if self.statx_capable {
+            statx_scroller(parent, ch, bar, &path, depth, &sent, self, tally)
+        } else {
+            regular_scroller(ch, bar, &path, depth, &sent, self, tally)
+        };
+        #[cfg(not(target_os = "linux"))]
+        let scrolled = regular_scroller(ch, bar, &path, depth, &sent, self, tally);
+
+        if let Err(err) = scrolled {
+            self.error(tally, bar, "Can't read the directory", &path, &err);
+            tally.end_dir(self);
+            // Notify the end of the directory
+            if let Err(e) = ch.send(build_dir_chan_done(path)) {
+                bar.println(format!("Can't signal the end of a directory: {e}"));
+            }
+            return;
+        }
+        self.scanned_files
+            .fetch_add(tally.current.res.files - files, Ordering::Relaxed);
+        tally.end_dir(self);
+
+        // Notify the end of the directory
+        let stats = mem::take(&mut tally.dir);
+        let done = if self.dir_stats {
+            build_dir_chan_done_with_stats(path, stats)
+        } else {
+            build_dir_chan_done(path)
+        };
+        if let Err(e) = ch.send(done) {
+            bar.println(format!("Can't signal the end of a directory: {e}"));
+        }
+    }
+}
+
+/// Totals of the files seen by a worker, merged at the end of the scan.
+struct Tally {
+    /// Totals of the directories done.
+    done: Totals,
+    /// Totals of the directory being scanned, added to the ones done at its end with checkpoints.
+    /// A checkpoint taken meanwhile leaves them out, the directory is scanned again on resume.
+    current: Totals,
+    /// Result holding the settings and no file, the totals of each directory start from it.
+    empty: objects::Result,
+    /// Time of the start of the scan in seconds since the epoch, for the ages.
+    now: i64,
+    /// Totals of the directory being scanned for the top directories.
+    dir: DirStats,
+    /// Subdirectories queued for the directory being scanned, only kept with checkpoints.
+    sent: Vec<OsString>,
+    /// Files with several names first seen in the directory being scanned, only kept with checkpoints.
+    linked: Vec<(u64, u64)>,
+    largest_files: usize,
+}
+
+/// Counters and ranked totals of a set of directories.
+#[derive(Clone)]
+struct Totals {
+    res: objects::Result,
+    ranked: Ranked,
+}
+
+impl Totals {
+    fn new(res: objects::Result) -> Totals {
+        Totals {
+            res,
+            ranked: Ranked::default(),
+        }
+    }
+
+    fn merge(&mut self, other: &Totals, largest_files: usize) {
+        self.res.merge(&other.res);
+        self.ranked.merge(&other.ranked, largest_files);
+    }
+}
+
+/// Workers stopped between two entries while a checkpoint is saved.
+#[derive(Default)]
+struct Held {
+    /// Number of the last checkpoint requested, the workers wait until it's released.
+    round: usize,
+    released: usize,
+    workers: Vec<HeldWorker>,
+}
+
+/// State handed over by a held worker, its directory being scanned is left to scan.
+struct HeldWorker {
+    done: Totals,
+    dir: PathBuf,
+    /// Subdirectories already queued for the directory.
+    sent: Vec<OsString>,
+    /// Files with several names first seen in the directory.
+    linked: Vec<(u64, u64)>,
+}
+
+/// A regular file as read by the scrollers.
+struct FileEntry {
+    /// Only given for the candidates to the largest files, empty otherwise.
+    path: PathBuf,
+    len: u64,
+    allocated: u64,
+    depth: usize,
+    time: Option<i64>,
+    owner: Option<(u32, u32)>,
+    extension: Option<String>,
+}
+
+impl Tally {
+    /// The given result holds the settings and no file.
+    fn new(res: objects::Result, largest_files: usize) -> Tally {
+        Tally {
+            now: epoch_seconds(res.start_time),
+            done: Totals::new(res.clone()),
+            current: Totals::new(res.clone()),
+            empty: res,
+            dir: DirStats::default(),
+            sent: Vec::new(),
+            linked: Vec::new(),
+            largest_files,
+        }
+    }
+
+    /// Count the names which are not valid UTF-8, they are scanned like the others.
+    fn count_name(&mut self, name: &OsStr) {
+        if name.to_str().is_none() {
+            self.current.res.non_utf8_names += 1;
+        }
+    }
+
+    /// Add the totals of the directory being scanned to the ones done.
+    fn merge_current(&mut self) {
+        let current = mem::replace(&mut self.current, Totals::new(self.empty.clone()));
+        self.done.merge(&current, self.largest_files);
+    }
+
+    /// End of a directory. Its totals are only kept apart for the checkpoints,
+    /// otherwise they are added to the ones done at the end of the scan.
+    fn end_dir(&mut self, walker: &Walker) {
+        if walker.checkpoints {
+            self.merge_current();
+            self.sent.clear();
+            self.linked.clear();
+            walker.raise_largest_threshold(&self.done.ranked.largest);
+        }
+    }
+
+    fn add_file(&mut self, file: FileEntry, walker: &Walker) {
+        let res = &mut self.current.res;
+        res.add_file(file.len, file.allocated, &walker.limits);
+        let depth = res.depth_mut(file.depth);
+        depth.files += 1;
+        depth.bytes += file.len;
+
+        if res.age_field.is_some() {
+            res.add_age(self.now, file.time, file.len, &walker.limits);
+        }
+
+        if walker.dir_stats {
+            self.dir.add_file(file.len, &walker.limits);
+        }
+
+        let ranked = &mut self.current.ranked;
+        if let Some((uid, gid)) = file.owner {
+            ranked
+                .users
+                .entry(uid)
+                .or_default()
+                .add_file(file.len, &walker.limits);
+            ranked
+                .groups
+                .entry(gid)
+                .or_default()
+                .add_file(file.len, &walker.limits);
+        }
+
+        if let Some(extension) = file.extension {
+            ranked
+                .extensions
+                .entry(extension)
+                .or_insert_with_key(|e| ExtensionStats::new(e.clone(), &walker.limits))
+                .add_file(file.len, &walker.limits);
+        }
+
+        if !file.path.as_os_str().is_empty() {
+            ranked.largest.push(Reverse((file.len, Reverse(file.path))));
+            if ranked.largest.len() > self.largest_files {
+                ranked.largest.pop();
+            }
+            walker.raise_largest_threshold(&ranked.largest);
+        }
+    }
+}
+
+/// A directory waiting for a worker to open it and scroll its entries.
+struct DirJob {
+    path: PathBuf,
+    depth: usize,
+    /// Subdirectories queued before the checkpoint the scan is resumed from, not queued again.
+    sent: HashSet<OsString>,
+    /// Open parent directory the directory is opened from, if kept.
+    #[cfg(target_os = "linux")]
+    parent: Option<ParentDir>,
+}
+
+impl DirJob {
+    /// A directory opened from its full path.
+    fn new(path: PathBuf, depth: usize) -> DirJob {
+        DirJob {
+            path,
+            depth,
+            sent: HashSet::new(),
+            #[cfg(target_os = "linux")]
+            parent: None,
+        }
+    }
+}
+
+/// Directories dispatched to the workers, with the totals aggregated from their messages.
+struct Dispatch<'a> {
+    pool: &'a Pool<DirJob, Tally>,
+    res: objects::Result,
+    max_depth: Option<usize>,
+    top_dirs_depth: usize,
+    root_depth: usize,
+    dir_totals: HashMap<PathBuf, DirStats>,
+    /// Directories queued or being scanned, with their depth.
+    pending: HashMap<PathBuf, usize>,
+    /// Directories left once the scan is stopped, with their depth.
+    unscanned: Vec<(PathBuf, usize)>,
+}
+
+impl Dispatch<'_> {
+    /// Queue a directory, the workers take them as soon as they are idle.
+    fn push(&mut self, job: DirJob) {
+        self.pending.insert(job.path.clone(), job.depth);
+        self.pool.push(job);
+    }
+
+    fn handle(&mut self, received: ChanResponse, walker: &Walker) {
+        // Check the type of the given element
+        match received.t {
+            // If Dir
+            objects::ResponseType::Dir => {
+                self.res.directories += 1;
+                self.res.depth_mut(received.depth).directories += 1;
+
+                // The directories at the maximum depth are counted but not scanned
+                if self.max_depth.is_some_and(|max| received.depth >= max) {
+                    return;
+                }
+
+                // No more directories are dispatched once the scan is stopped
+                if walker.stopped() {
+                    self.unscanned.push((received.path, received.depth));
+                    return;
+                }
+
+                self.push(DirJob {
+                    path: received.path,
+                    depth: received.depth,
+                    sent: HashSet::new(),
+                    #[cfg(target_os = "linux")]
+                    parent: received.parent,
+                });
+            }
+            // If this signal a directory scan terminated
+            objects::ResponseType::DoneDir => {
+                // Add the totals of the directory to itself and its parents up to the depth limit
+                if let Some(stats) = &received.stats {
+                    for dir in received.path.ancestors() {
+                        let depth = dir.components().count().saturating_sub(self.root_depth);
+                        if depth == 0 {
+                            break;
+                        }
+                        if depth <= self.top_dirs_depth {
+                            self.dir_totals
+                                .entry(dir.to_path_buf())
+                                .or_default()
+                                .merge(stats);
+                        }
+                    }
+                }
+                self.pending.remove(&received.path);
+            }
+            objects::ResponseType::Skipped => {
+                self.pending.remove(&received.path);
+                self.unscanned.push((received.path, received.depth));
+            }
+        }
+    }
+}
+
+/// Largest files with their path, the smallest one on top to be dropped first.
+/// Among the files with the same size the greatest path is dropped first, and listed last.
+type Largest = BinaryHeap<Reverse<(u64, Reverse<PathBuf>)>>;
+
+/// Totals ranked at the end of the scan: the owners, the extensions and the largest files.
+#[derive(Clone, Default)]
+struct Ranked {
+    largest: Largest,
+    users: HashMap<u32, OwnerStats>,
+    groups: HashMap<u32, OwnerStats>,
+    extensions: HashMap<String, ExtensionStats>,
+}
+
+impl Ranked {
+    fn restore(checkpoint: &Checkpoint) -> Ranked {
+        Ranked {
+            largest: checkpoint
+                .largest
+                .iter()
+                .map(|(size, path)| Reverse((*size, Reverse(path.0.clone()))))
+                .collect(),
+            users: checkpoint.users.iter().cloned().collect(),
+            groups: checkpoint.groups.iter().cloned().collect(),
+            extensions: checkpoint.extensions.iter().cloned().collect(),
+        }
+    }
+
+    /// Merge the totals of other directories, keeping the given number of largest files.
+    fn merge(&mut self, other: &Ranked, largest_files: usize) {
+        self.largest.extend(other.largest.iter().cloned());
+        while self.largest.len() > largest_files {
+            self.largest.pop();
+        }
+        for (uid, stats) in &other.users {
+            self.users.entry(*uid).or_default().merge(stats);
+        }
+        for (gid, stats) in &other.groups {
+            self.groups.entry(*gid).or_default().merge(stats);
+        }
+        for (extension, stats) in &other.extensions {
+            match self.extensions.get_mut(extension) {
+                Some(e) => e.merge(stats),
+                None => {
+                    self.extensions.insert(extension.clone(), stats.clone());
+                }
+            }
+        }
+    }
+}
+
+/// Flags opening the directories scrolled with statx.
+#[cfg(target_os = "linux")]
+const DIR_FLAGS: rustix::fs::OFlags = rustix::fs::OFlags::RDONLY
+    .union(rustix::fs::OFlags::DIRECTORY)
+    .union(rustix::fs::OFlags::CLOEXEC);
+
+/// Open a directory from its full path. A path longer than PATH_MAX is opened in several steps,
+/// each one relative to the directory opened by the previous step.
+#[cfg(target_os = "linux")]
+fn open_dir_path(path: &Path) -> io::Result<rustix::fd::OwnedFd> {
+    use rustix::fs::{cwd, openat, Mode};
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let mut dir = None;
+    let mut step = PathBuf::new();
+    let mut components = path.components().peekable();
+    while let Some(component) = components.next() {
+        step.push(component);
+        let next = components.peek().map_or(0, |c| c.as_os_str().len() + 1);
+        if components.peek().is_some() && step.as_os_str().len() + next < PATH_MAX {
+            continue;
+        }
+        let name = CString::new(step.as_os_str().as_bytes())?;
+        let opened = match &dir {
+            Some(dir) => openat(dir, name.as_c_str(), DIR_FLAGS, Mode::empty())?,
+            None => openat(cwd(), name.as_c_str(), DIR_FLAGS, Mode::empty())?,
+        };
+        dir = Some(opened);
+        step = PathBuf::new();
+    }
+    match dir {
+        Some(dir) => Ok(dir),
+        // An empty path, opened as is to get the error of the system
+        None => Ok(openat(cwd(), path, DIR_FLAGS, Mode::empty())?),
+    }
+}
+
+/// Scroll a directory opened relative to its parent, the entries are read with statx relative to it.
+/// Only the root and the directories whose parent was not kept are opened from their full path.
+#[cfg(target_os = "linux")]
+#[allow(clippy::too_many_arguments)]
+fn statx_scroller(
+    parent: Option<ParentDir>,
+    ch: &Sender<ChanResponse>,
+    bar: &ProgressBar,
+    path: &Path,
+    depth: usize,
+    sent: &HashSet<OsString>,
+    walker: &Walker,
+    tally: &mut Tally,
+) -> std::io::Result<()> {
+    use rustix::fs::{makedev, openat, statx, AtFlags, FileType, Mode, RawDir, StatxFlags};
+    use std::os::unix::ffi::OsStrExt;
+
+    let dir = match parent {
+        Some((parent, name)) => openat(&*parent, name.as_os_str(), DIR_FLAGS, Mode::empty())?,
+        None => open_dir_path(path)?,
+    };
+    // Every directory kept for its subdirectories holds a descriptor until they are all opened
+    let keep = walker.open_dirs.load(Ordering::Relaxed) < MAX_OPEN_DIRS;
+    let dir = Arc::new(OpenDir::new(dir, &walker.open_dirs));
+
+    let mut mask = StatxFlags::SIZE | StatxFlags::TYPE | StatxFlags::INO | StatxFlags::NLINK;
+    if walker.allocated {
+        mask |= StatxFlags::BLOCKS;
+    }
+    if walker.owners {
+        mask |= StatxFlags::UID | StatxFlags::GID;
+    }
+    match walker.age {
+        Some(TimeField::Modified) => mask |= StatxFlags::MTIME,
+        Some(TimeField::Accessed) => mask |= StatxFlags::ATIME,
+        Some(TimeField::Changed) => mask |= StatxFlags::CTIME,
+        Some(TimeField::Created) => mask |= StatxFlags::BTIME,
+        None => {}
+    }
+
+    // Large enough for any entry, the names are at most 255 bytes long
+    let mut buf = Vec::with_capacity(DIRENT_BUFFER_SIZE);
+    let mut entries = RawDir::new(&*dir, buf.spare_capacity_mut());
+    while let Some(entry) = entries.next() {
+        walker.hold(tally, path);
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                walker.error(tally, bar, "Can't read the entries of", path, &err.into());
+                break;
+            }
+        };
+        let file_name = entry.file_name();
+        if file_name.to_bytes() == b"." || file_name.to_bytes() == b".." {
+            continue;
+        }
+        let name = OsStr::from_bytes(file_name.to_bytes());
+        tally.count_name(name);
+
+        let stat_entry = || {
+            statx(
+                &*dir,
+                file_name,
+                AtFlags::SYMLINK_NOFOLLOW | AtFlags::STATX_DONT_SYNC,
+                mask,
+            )
+        };
+
+        // Some filesystems don't give the type in the entries, it's read with the other fields
+        let mut stat = None;
+        let mut file_type = entry.file_type();
+        if file_type == FileType::Unknown {
+            match stat_entry() {
+                Ok(s) => {
+                    file_type = FileType::from_raw_mode(u32::from(s.stx_mode));
+                    stat = Some(s);
+                }
+                Err(err) => {
+                    let path = path.join(name);
+                    walker.error(tally, bar, "Can't get the type of", &path, &err.into());
+                    continue;
+                }
+            }
+        }
+
+        if walker.pruned(tally, path, name, file_type == FileType::Directory) {
+            continue;
+        }
+        if file_type == FileType::Directory {
+            if sent.contains(name) {
+                continue;
+            }
+            let dev = || {
+                stat.or_else(|| stat_entry().ok())
+                    .map(|s| makedev(s.stx_dev_major, s.stx_dev_minor))
+            };
+            if walker.other_fs(tally, path, name, dev) {
+                continue;
+            }
+            let sub_path = path.join(name);
+            let queued = if keep || sub_path.as_os_str().len() >= PATH_MAX {
+                let parent = (Arc::clone(&dir), name.to_os_string());
+                build_dir_chan_at(parent, sub_path, depth + 1)
+            } else {
+                build_dir_chan(sub_path, depth + 1)
+            };
+            walker.send_dir(tally, ch, bar, name, queued);
+            continue;
+        }
+
+        let stat = match stat.map_or_else(stat_entry, Ok) {
+            Ok(stat) => stat,
+            Err(err) => {
+                walker.error(tally, bar, "Can't stat", &path.join(name), &err.into());
+                continue;
+            }
+        };
+        // Only the regular files are accounted in the size ranges
+        let kind = match FileType::from_raw_mode(u32::from(stat.stx_mode)) {
+            FileType::RegularFile => EntryKind::Regular,
+            FileType::Symlink => EntryKind::Symlink,
+            FileType::Fifo => EntryKind::Fifo,
+            FileType::Socket => EntryKind::Socket,
+            FileType::CharacterDevice => EntryKind::CharDevice,
+            FileType::BlockDevice => EntryKind::BlockDevice,
+            _ => EntryKind::Unknown,
+        };
+        if kind != EntryKind::Regular {
+            tally.current.res.add_entry(kind);
+            continue;
+        }
+
+        let dev = makedev(stat.stx_dev_major, stat.stx_dev_minor);
+        if !walker.first_link(tally, u64::from(stat.stx_nlink), dev, stat.stx_ino) {
+            continue;
+        }
+        tally.add_file(
+            FileEntry {
+                path: walker.largest_candidate(stat.stx_size, path, name),
+                len: stat.stx_size,
+                allocated: stat.stx_blocks * 512,
+                depth: depth + 1,
+                time: statx_time(&stat, walker.age),
+                owner: walker.owners.then_some((stat.stx_uid, stat.stx_gid)),
+                extension: walker.extension(name),
+            },
+            walker,
+        );
+    }
+    Ok(())
+}
+
+fn regular_scroller(
+    ch: &Sender<ChanResponse>,
+    bar: &ProgressBar,
+    path: &Path,
+    depth: usize,
+    sent: &HashSet<OsString>,
+    walker: &Walker,
+    tally: &mut Tally,
+) -> std::io::Result<()> {
+    for entry in fs::read_dir(path)? {
+        walker.hold(tally, path);
+        match entry {
+            Ok(entry) => match entry.metadata() {
+                Ok(metadata) => {
+                    let name = entry.file_name();
+                    tally.count_name(&name);
+                    if walker.pruned(tally, path, &name, metadata.is_dir()) {
+                        continue;
+                    }
+                    if metadata.is_dir() {
+                        if sent.contains(&name) {
+                            continue;
+                        }
+                        if walker.other_fs(tally, path, &name, || file_dev(&metadata)) {
+                            continue;
+                        }
+                        let queued = build_dir_chan(entry.path(), depth + 1);
+                        walker.send_dir(tally, ch, bar, &name, queued);
+                    } else if metadata.is_file() {
+                        #[cfg(unix)]
+                        {
+                            use std::os::unix::fs::MetadataExt;
+                            let (nlink, dev, ino) =
+                                (metadata.nlink(), metadata.dev(), metadata.ino());
+                            if !walker.first_link(tally, nlink, dev, ino) {
+                                continue;
+                            }
+                        }
+                        tally.add_file(
+                            FileEntry {
+                                path: walker.largest_candidate(metadata.len(), path, &name),
+                                len: metadata.len(),
+                                allocated: allocated_size(&metadata, walker.allocated),
+                                depth: depth + 1,
+                                time: file_time(&metadata, walker.age),
+                                owner: file_owner(&metadata, walker.owners),
+                                extension: walker.extension(&name),
+                            },
+                            walker,
+                        );
+                    } else {
+                        tally
+                            .current
+                            .res
+                            .add_entry(entry_kind(&metadata.file_type()));
+                    }
+                }
+                Err(err) => {
+                    walker.error(tally, bar, "Can't get the metadata of", &entry.path(), &err);
+                }
+            },
+            Err(err) => {
+                walker.error(tally, bar, "Can't read the entries of", path, &err);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Device of a file, not available on Windows.
+fn file_dev(metadata: &fs::Metadata) -> Option<u64> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        Some(metadata.dev())
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = metadata;
+        None
+    }
+}
+
+/// Write an error as a line of the error log: the error number, its class and the raw path, separated by tabs.
+fn write_error<W: Write>(
+    log: &mut W,
+    path: &Path,
+    kind: ErrorKind,
+    err: &io::Error,
+) -> io::Result<()> {
+    write!(
+        log,
+        "{}\t{}\t",
+        err.raw_os_error().unwrap_or(0),
+        kind.name()
+    )?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        log.write_all(path.as_os_str().as_bytes())?;
+    }
+    #[cfg(not(unix))]
+    log.write_all(path.to_string_lossy().as_bytes())?;
+    writeln!(log)
+}
+
+/// User and group ids of a file, if requested.
+fn file_owner(metadata: &fs::Metadata, owners: bool) -> Option<(u32, u32)> {
+    #[cfg(unix)]
+    if owners {
+        use std::os::unix::fs::MetadataExt;
+        return Some((metadata.uid(), metadata.gid()));
+    }
+    #[cfg(not(unix))]
+    let _ = (metadata, owners);
+
+    None
+}
+
+/// Name the owners and sort them by size, the biggest first, then by id.
+fn owner_list(
+    owners: HashMap<u32, OwnerStats>,
+    name: fn(u32) -> Option<String>,
+) -> Vec<OwnerStats> {
+    let mut list: Vec<OwnerStats> = owners
+        .into_iter()
+        .map(|(id, mut stats)| {
+            stats.id = id;
+            stats.name = name(id).unwrap_or_else(|| id.to_string());
+            stats
+        })
+        .collect();
+    list.sort_by_key(|o| (Reverse(o.bytes), o.id));
+    list
+}
+
+#[cfg(unix)]
+fn user_name(uid: u32) -> Option<String> {
+    uzers::get_user_by_uid(uid).map(|u| u.name().to_string_lossy().to_string())
+}
+
+#[cfg(unix)]
+fn group_name(gid: u32) -> Option<String> {
+    uzers::get_group_by_gid(gid).map(|g| g.name().to_string_lossy().to_string())
+}
+
+#[cfg(not(unix))]
+fn user_name(_uid: u32) -> Option<String> {
+    None
+}
+
+#[cfg(not(unix))]
+fn group_name(_gid: u32) -> Option<String> {
+    None
+}
+
+/// Seconds since the epoch, negative before.
+fn epoch_seconds(time: time::SystemTime) -> i64 {
+    match time.duration_since(time::UNIX_EPOCH) {
+        Ok(d) => d.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs() as i64),
+    }
+}
+
+/// Chosen timestamp of a file in seconds since the epoch, if available.
+#[cfg(target_os = "linux")]
+fn statx_time(stat: &rustix::fs::Statx, field: Option<TimeField>) -> Option<i64> {
+    use rustix::fs::StatxFlags;
+
+    // The returned mask tells which fields are filled, the birth time is not always available
+    let filled = StatxFlags::from_bits_truncate(stat.stx_mask);
+    match field? {
+        TimeField::Modified if filled.contains(StatxFlags::MTIME) => Some(stat.stx_mtime.tv_sec),
+        TimeField::Accessed if filled.contains(StatxFlags::ATIME) => Some(stat.stx_atime.tv_sec),
+        TimeField::Changed if filled.contains(StatxFlags::CTIME) => Some(stat.stx_ctime.tv_sec),
+        TimeField::Created if filled.contains(StatxFlags::BTIME) => Some(stat.stx_btime.tv_sec),
+        _ => None,
+    }
+}
+
+/// Chosen timestamp of a file in seconds since the epoch, if available.
+fn file_time(metadata: &fs::Metadata, field: Option<TimeField>) -> Option<i64> {
+    let time = match field? {
+        TimeField::Modified => metadata.modified(),
+        TimeField::Accessed => metadata.accessed(),
+        TimeField::Created => metadata.created(),
+        TimeField::Changed => {
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::MetadataExt;
+                return Some(metadata.ctime());
+            }
+            #[cfg(not(unix))]
+            return None;
+        }
+    };
+    time.ok().map(epoch_seconds)
+}
+
+/// Kind of a non directory entry from its metadata.
+fn entry_kind(file_type: &fs::FileType) -> EntryKind {
+    if file_type.is_file() {
+        return EntryKind::Regular;
+    }
+    if file_type.is_symlink() {
+        return EntryKind::Symlink;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+        if file_type.is_fifo() {
+            return EntryKind::Fifo;
+        }
+        if file_type.is_socket() {
+            return EntryKind::Socket;
+        }
+        if file_type.is_char_device() {
+            return EntryKind::CharDevice;
+        }
+        if file_type.is_block_device() {
+            return EntryKind::BlockDevice;
+        }
+    }
+    EntryKind::Unknown
+}
+
+/// Allocated size on disk in bytes, 0 if not requested or not available.
+fn allocated_size(metadata: &fs::Metadata, allocated: bool) -> u64 {
+    #[cfg(unix)]
+    if allocated {
+        use std::os::unix::fs::MetadataExt;
+        return metadata.blocks() * 512;
+    }
+    #[cfg(not(unix))]
+    let _ = (metadata, allocated);
+
+    0
+}
+
+#[cfg(target_os = "linux")]
+fn statx_supported(path: &Path, statx: bool, verbose: bool) -> bool {
+    // This is to disable statx manually
+    if !statx {
+        if verbose {
+            eprintln!("{:}", "statx was disabled manually".yellow());
+        }
+
+        return false;
+    }
+
+    let entries = match fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!(
+                "the path can't be read as a directory: {:}",
+                e.to_string().red()
+            );
+            return false;
+        }
+    };
+
+    // Get the fist file from the directory
+    for entry in entries {
+        match entry {
+            Ok(entry) => match entry.file_type() {
+                Ok(t) => {
+                    if t.is_dir() {
+                        continue;
+                    } else if t.is_file() {
+                        return test_statx_on_file(path, verbose, entry);
+                    }
+                }
+                Err(e) => {
+                    eprintln!(
+                        "can't get type of file {:?} with error: {:}",
+                        entry.file_name().as_os_str(),
+                        e.to_string().red()
+                    );
+                    continue;
+                }
+            },
+            Err(e) => {
+                eprintln!(
+                    "can't get the content from the directory: {:}",
+                    e.to_string().red()
+                );
+            }
+        }
+    }
+
+    false
+}
+
+#[cfg(target_os = "linux")]
+fn test_statx_on_file(path: &Path, verbose: bool, entry: fs::DirEntry) -> bool {
+    use rustix::fs::{cwd, openat, statx, AtFlags, Mode, OFlags, StatxFlags};
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let return_false = |message: String, verbose| -> bool {
+        // If verbose it mention the fact that Statx is not supported on this system
+        if verbose {
+            // Print the given message
+            eprintln!("{message:}");
+            eprintln!("statx is {:} supported on this system", "NOT".red());
+        }
+
+        // Return false
+        false
+    };
+
+    // Generate a CString for the directory
+    let dir_c_str = match CString::new(path.as_os_str().as_bytes()) {
+        Ok(cs) => cs,
+        Err(e) => {
+            return return_false(
+                format!(
+                    "can't make the directory C string: {:}",
+                    e.to_string().red()
+                ),
+                verbose,
+            );
+        }
+    };
+
+    // Open the directory from the CString directory
+    let dir = match openat(
+        cwd(),
+        &dir_c_str,
+        OFlags::RDONLY | OFlags::DIRECTORY,
+        Mode::empty(),
+    ) {
+        Ok(d) => d,
+        Err(e) => {
+            return return_false(
+                format!("can't open file with statx lib: {:}", e.to_string().red()),
+                verbose,
+            );
+        }
+    };
+
+    // Generate a CString for the file
+    let file_c_str = match CString::new(entry.file_name().as_bytes()) {
+        Ok(cs) => cs,
+        Err(e) => {
+            return return_false(
+                format!(
+                    "can't make CString from file name: {:}",
+                    e.to_string().red()
+                ),
+                verbose,
+            );
+        }
+    };
+
+    // Extract statx based on the previously generated dir and file path via CString
+    match statx(
+        &dir,
+        &file_c_str,
+        AtFlags::SYMLINK_NOFOLLOW | AtFlags::STATX_DONT_SYNC,
+        StatxFlags::SIZE | StatxFlags::TYPE,
+    ) {
+        Ok(stat) => stat,
+        Err(e) => {
+            return return_false(
+                format!("can't get stat with statx: {:}", e.to_string().red()),
+                verbose,
+            );
+        }
+    };
+
+    //Display to user that statx is supported on the system
+    if verbose {
+        eprintln!("statx is {:} on this system", "supported".green());
+    }
+
+    true
+}
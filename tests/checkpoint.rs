@@ -0,0 +1,111 @@
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use fs_scan::checkpoint::Checkpoint;
+use fs_scan::objects::Result;
+use fs_scan::Scanner;
+
+/// A large directory mixing files and subdirectories, with files linked from several directories.
+fn build_tree(root: &Path) {
+    let big = root.join("big");
+    fs::create_dir_all(&big).unwrap();
+    for i in 0..8000 {
+        fs::write(big.join(format!("f{i}")), vec![b'x'; i % 3000]).unwrap();
+        if i % 100 == 0 {
+            let sub = big.join(format!("d{i}"));
+            fs::create_dir(&sub).unwrap();
+            fs::write(sub.join("file"), vec![b'y'; i]).unwrap();
+            #[cfg(unix)]
+            fs::hard_link(big.join(format!("f{i}")), sub.join("link")).unwrap();
+        }
+    }
+    // The detection of statx needs a file in the scanned directory
+    fs::write(root.join("file"), "content").unwrap();
+}
+
+fn assert_same_totals(resumed: &Result, full: &Result) {
+    assert_eq!(resumed.files, full.files);
+    assert_eq!(resumed.bytes, full.bytes);
+    assert_eq!(resumed.extra_links, full.extra_links);
+    assert_eq!(resumed.directories, full.directories);
+    assert_eq!(
+        serde_json::to_value(&resumed.buckets).unwrap(),
+        serde_json::to_value(&full.buckets).unwrap()
+    );
+}
+
+/// Resume from the checkpoints saved while the directories are scanned, as if the scan was killed,
+/// the totals must be the ones of an uninterrupted scan.
+fn resume_from_periodic_checkpoints(statx: bool) {
+    let dir =
+        std::env::temp_dir().join(format!("fs-scan-checkpoint-{}-{statx}", std::process::id()));
+    let scanned = dir.join("scanned");
+    build_tree(&scanned);
+    let checkpoint = dir.join("checkpoint.json");
+
+    let full = Scanner::new(&scanned).statx(statx).max_threads(4).scan();
+
+    // Keep a copy of each checkpoint, the file is removed once the scan is complete
+    let done = Arc::new(AtomicBool::new(false));
+    let watcher = {
+        let done = Arc::clone(&done);
+        let checkpoint = checkpoint.clone();
+        thread::spawn(move || {
+            let mut copies: Vec<Vec<u8>> = Vec::new();
+            while !done.load(Ordering::Relaxed) && copies.len() < 10 {
+                if let Ok(content) = fs::read(&checkpoint) {
+                    if copies.last() != Some(&content) {
+                        copies.push(content);
+                    }
+                }
+                thread::sleep(Duration::from_millis(1));
+            }
+            copies
+        })
+    };
+    let res = Scanner::new(&scanned)
+        .statx(statx)
+        .max_threads(4)
+        .checkpoint(Some(checkpoint.clone()))
+        .checkpoint_interval(Duration::from_millis(1))
+        .scan();
+    done.store(true, Ordering::Relaxed);
+    let copies = watcher.join().unwrap();
+    assert_same_totals(&res, &full);
+    assert!(!copies.is_empty(), "no checkpoint saved during the scan");
+
+    let mut in_flight = false;
+    for content in copies {
+        let saved: serde_json::Value = serde_json::from_slice(&content).unwrap();
+        in_flight |= saved["sent"]
+            .as_array()
+            .is_some_and(|sent| !sent.is_empty());
+        fs::write(&checkpoint, &content).unwrap();
+        let resumed = Scanner::new(&scanned)
+            .statx(statx)
+            .max_threads(4)
+            .resume(Checkpoint::load(&checkpoint).unwrap())
+            .unwrap()
+            .scan();
+        assert_same_totals(&resumed, &full);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(
+        in_flight,
+        "no checkpoint saved in the middle of a directory"
+    );
+}
+
+#[test]
+fn resume_from_periodic_checkpoints_with_statx() {
+    resume_from_periodic_checkpoints(true);
+}
+
+#[test]
+fn resume_from_periodic_checkpoints_without_statx() {
+    resume_from_periodic_checkpoints(false);
+}
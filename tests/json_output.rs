@@ -0,0 +1,57 @@
+use std::fs;
+use std::process::Command;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+/// The JSON document printed on the standard output must not be mixed with the other messages.
+#[test]
+fn json_on_stdout_with_csv_and_verbose() {
+    let dir = std::env::temp_dir().join(format!("fs-scan-json-{}", std::process::id()));
+    let scanned = dir.join("scanned");
+    fs::create_dir_all(scanned.join("sub")).unwrap();
+    fs::write(scanned.join("file"), "content").unwrap();
+
+    // The CSV file is written in the current directory
+    let output = Command::new(env!("CARGO_BIN_EXE_fs-scan"))
+        .args(["--format", "json", "-s", "-v"])
+        .arg(&scanned)
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert!(output.status.success());
+    let res: serde_json::Value =
+        serde_json::from_slice(&output.stdout).expect("the standard output is a JSON document");
+    assert_eq!(res["files"], 1);
+    assert_eq!(res["directories"], 1);
+}
+
+/// Same on a resumed scan, from the checkpoint of a scan stopped before its first directory.
+#[test]
+fn json_on_stdout_when_resuming() {
+    let dir = std::env::temp_dir().join(format!("fs-scan-resume-{}", std::process::id()));
+    let scanned = dir.join("scanned");
+    fs::create_dir_all(scanned.join("sub")).unwrap();
+    fs::write(scanned.join("file"), "content").unwrap();
+    let checkpoint = dir.join("checkpoint.json");
+
+    let res = fs_scan::Scanner::new(&scanned)
+        .checkpoint(Some(checkpoint.clone()))
+        .stop_flag(Arc::new(AtomicBool::new(true)))
+        .scan();
+    assert!(res.interrupted);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_fs-scan"))
+        .args(["--format", "json", "--resume", "--checkpoint"])
+        .arg(&checkpoint)
+        .output()
+        .unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert!(output.status.success());
+    let res: serde_json::Value =
+        serde_json::from_slice(&output.stdout).expect("the standard output is a JSON document");
+    assert_eq!(res["files"], 1);
+    assert_eq!(res["interrupted"], false);
+}
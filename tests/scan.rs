@@ -0,0 +1,209 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use fs_scan::objects::Result;
+use fs_scan::Scanner;
+
+/// Directory removed at the end of the test, with a file at its root so statx is detected.
+struct TempTree(PathBuf);
+
+impl TempTree {
+    fn new(name: &str) -> TempTree {
+        let path = std::env::temp_dir().join(format!("fs-scan-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        fs::write(path.join("root_file"), "root").unwrap();
+        TempTree(path)
+    }
+
+    /// Create the parent directories of the path and write the file.
+    fn file(&self, path: &str, len: usize) -> PathBuf {
+        let path = self.0.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, vec![b'x'; len]).unwrap();
+        path
+    }
+}
+
+impl Drop for TempTree {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Results of the scans of the tree with and without statx, each one checked the same way.
+fn scan_both(path: &Path, configure: impl Fn(Scanner) -> Scanner) -> Vec<Result> {
+    [true, false]
+        .iter()
+        .map(|&statx| {
+            let res = configure(Scanner::new(path).statx(statx).max_threads(4)).scan();
+            assert_eq!(res.statx, statx && cfg!(target_os = "linux"));
+            res
+        })
+        .collect()
+}
+
+#[cfg(unix)]
+#[test]
+fn hard_links_are_counted_once() {
+    let tree = TempTree::new("links");
+    let file = tree.file("a/file", 100);
+    fs::create_dir(tree.0.join("b")).unwrap();
+    fs::hard_link(&file, tree.0.join("a/link")).unwrap();
+    fs::hard_link(&file, tree.0.join("b/link")).unwrap();
+
+    for res in scan_both(&tree.0, |scanner| scanner) {
+        assert_eq!(res.files, 2);
+        assert_eq!(res.bytes, 104);
+        assert_eq!(res.extra_links, 2);
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn special_entries_are_counted_by_kind() {
+    use fs_scan::objects::EntryKind;
+    use rustix::fs::{cwd, mknodat, FileType, Mode};
+    use std::os::unix::fs::symlink;
+    use std::os::unix::net::UnixListener;
+
+    let tree = TempTree::new("kinds");
+    let file = tree.file("dir/file", 10);
+    symlink(&file, tree.0.join("file_link")).unwrap();
+    // The links to directories are not followed
+    symlink(tree.0.join("dir"), tree.0.join("dir_link")).unwrap();
+    mknodat(cwd(), tree.0.join("fifo"), FileType::Fifo, Mode::RUSR, 0).unwrap();
+    UnixListener::bind(tree.0.join("socket")).unwrap();
+
+    for res in scan_both(&tree.0, |scanner| scanner) {
+        assert_eq!(res.files, 2);
+        assert_eq!(res.directories, 1);
+        assert_eq!(res.entries(EntryKind::Regular), 2);
+        assert_eq!(res.entries(EntryKind::Symlink), 2);
+        assert_eq!(res.entries(EntryKind::Fifo), 1);
+        assert_eq!(res.entries(EntryKind::Socket), 1);
+    }
+}
+
+#[test]
+fn one_file_system_scans_the_whole_tree_of_the_filesystem() {
+    let tree = TempTree::new("one-fs");
+    tree.file("a/b/file", 10);
+
+    for res in scan_both(&tree.0, |scanner| scanner.one_file_system(true)) {
+        assert_eq!(res.files, 2);
+        assert_eq!(res.directories, 2);
+        assert!(res.skipped_mounts.is_empty());
+    }
+}
+
+/// The mounts can't be created without privileges, the ones of the system are used instead.
+#[cfg(target_os = "linux")]
+#[test]
+fn one_file_system_skips_the_mounts() {
+    use std::os::unix::fs::MetadataExt;
+
+    let root_dev = fs::metadata("/").unwrap().dev();
+    let other_fs = match fs::metadata("/proc") {
+        Ok(proc) => proc.dev() != root_dev,
+        Err(_) => false,
+    };
+    if !other_fs {
+        return;
+    }
+    // statx is only used if the root holds a regular file
+    for &statx in &[true, false] {
+        let res = Scanner::new("/")
+            .statx(statx)
+            .one_file_system(true)
+            .max_depth(Some(1))
+            .scan();
+        assert!(res.skipped_mounts.contains(&"/proc".to_string()));
+    }
+}
+
+#[test]
+fn directories_at_the_max_depth_are_counted_but_not_scanned() {
+    let tree = TempTree::new("depth");
+    tree.file("a/file", 10);
+    tree.file("a/b/file", 20);
+    tree.file("a/b/c/file", 30);
+
+    for res in scan_both(&tree.0, |scanner| scanner.max_depth(Some(2))) {
+        assert_eq!(res.files, 2);
+        assert_eq!(res.bytes, 14);
+        assert_eq!(res.directories, 2);
+        assert_eq!(res.depths.len(), 3);
+        for depth in &res.depths[1..] {
+            assert_eq!((depth.files, depth.directories), (1, 1));
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn non_utf8_names_are_scanned() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let tree = TempTree::new("non-utf8");
+    let root = tree.0.join(OsStr::from_bytes(b"root-\xff"));
+    let dir = root.join(OsStr::from_bytes(b"dir-\xfe"));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(root.join("file"), "root").unwrap();
+    fs::write(dir.join(OsStr::from_bytes(b"file-\xfd")), "content").unwrap();
+
+    for res in scan_both(&root, |scanner| scanner) {
+        assert_eq!(res.path, root);
+        assert_eq!(res.files, 2);
+        assert_eq!(res.bytes, 11);
+        assert_eq!(res.directories, 1);
+        assert_eq!(res.non_utf8_names, 2);
+    }
+}
+
+#[test]
+fn errors_are_counted_by_class_and_logged() {
+    use fs_scan::objects::ErrorKind;
+
+    let tree = TempTree::new("errors");
+    let missing = tree.0.join("missing");
+    let log = tree.0.join("errors.log");
+
+    // statx is not detected without a file in the scanned directory
+    for &statx in &[true, false] {
+        let res = Scanner::new(&missing)
+            .statx(statx)
+            .error_log(Some(log.clone()))
+            .scan();
+        assert_eq!(res.errors(ErrorKind::NotFound), 1);
+        assert_eq!(
+            fs::read_to_string(&log).unwrap(),
+            format!("2\t{}\t{}\n", ErrorKind::NotFound.name(), missing.display())
+        );
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn unreadable_directories_are_counted_as_permission_denied() {
+    use fs_scan::objects::ErrorKind;
+    use std::os::unix::fs::PermissionsExt;
+
+    // The permissions don't apply to root
+    if uzers::get_effective_uid() == 0 {
+        return;
+    }
+    let tree = TempTree::new("denied");
+    let locked = tree.file("locked/file", 10).parent().unwrap().to_path_buf();
+    fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+
+    let results = scan_both(&tree.0, |scanner| scanner);
+    // Let the tree be removed
+    fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+    for res in results {
+        assert_eq!(res.files, 1);
+        assert_eq!(res.directories, 1);
+        assert_eq!(res.errors(ErrorKind::PermissionDenied), 1);
+    }
+}